@@ -27,7 +27,8 @@ fn parse_table(ast: &syn::DeriveInput) -> TokenStream {
 
     let column_info = get_column_info(&fields);
 
-    let build_generate_enum = build_generate_enum(struct_name, &column_info, &fields);
+    let build_generate_enum =
+        build_generate_enum(struct_name, table_name.clone(), &column_info, &fields);
 
     let build_impl_try_get = build_impl_try_get(struct_name, &fields);
 
@@ -92,6 +93,7 @@ fn get_table_info(ast: &syn::DeriveInput) -> (String, bool) {
 /// generate enum
 fn build_generate_enum(
     struct_name: &Ident,
+    table_name: String,
     column_info: &HashMap<String, proc_macro2::TokenStream>,
     fields: &[ColumnInformation],
 ) -> proc_macro2::TokenStream {
@@ -121,6 +123,10 @@ fn build_generate_enum(
                     )*
                 }
             }
+
+            fn table_name(&self) -> Option<String> {
+                Some(#table_name.into())
+            }
         }
     )
 }
@@ -139,15 +145,22 @@ fn build_impl_try_get(
         let is_optional = f.field_type.starts_with("Option<");
         if is_optional {
             entity_try_get.push(quote!(
-                if let Ok(#column_ident) =  row.try_get(#alias) {
+                if let Ok(#column_ident) =  row.try_get(format!("{}{}", prefix, #alias).as_str()) {
                     if let Some(value) = #column_ident {
                         entity.#column_ident = value;
+                        found |= entity.#column_ident.is_some();
                     }
                 }
             ));
         } else {
             entity_try_get.push(quote!(
-                if let Ok(#column_ident) =  row.try_get(#alias) {
+                let column = format!("{}{}", prefix, #alias);
+                if let Ok(#column_ident) =  row.try_get(column.as_str()) {
+                    // some drivers decode NULL into a default, so the outer side of
+                    // a join is only found on a column that is not NULL
+                    found |= row
+                        .try_get_raw(column.as_str())
+                        .is_ok_and(|value| !sqlx::ValueRef::is_null(&value));
                     entity.#column_ident = #column_ident;
                 }
 
@@ -156,12 +169,30 @@ fn build_impl_try_get(
     });
 
     quote!(
-        use sqlx::Row;
-        impl grapefruit::TryGetable for #struct_name {
-            fn try_get(res: &grapefruit::QueryResult) -> grapefruit::GrapefruitResult<Option<Self>> {
+        const _: () = {
+            use sqlx::Row;
+            impl grapefruit::TryGetable for #struct_name {
+                fn try_get(res: &grapefruit::QueryResult) -> grapefruit::GrapefruitResult<Option<Self>> {
+                    Ok(try_get_entity(res, "")?.map(|(entity, _)| entity))
+                }
+
+                fn try_get_prefixed(
+                    res: &grapefruit::QueryResult,
+                    prefix: &str,
+                ) -> grapefruit::GrapefruitResult<Option<Self>> {
+                    Ok(try_get_entity(res, prefix)?.and_then(|(entity, found)| found.then_some(entity)))
+                }
+            }
+
+            /// entity read from the `{prefix}{column}` columns, and whether any of them held a value
+            fn try_get_entity(
+                res: &grapefruit::QueryResult,
+                prefix: &str,
+            ) -> grapefruit::GrapefruitResult<Option<(#struct_name, bool)>> {
                 let entity = match res.row.as_ref() {
                     Some(row) => {
                         let mut entity = #struct_name::default();
+                        let mut found = false;
                         match row {
                             #[cfg(feature = "mysql")]
                             grapefruit::QueryRow::MySql(row) => {
@@ -176,14 +207,14 @@ fn build_impl_try_get(
                                 #(#entity_try_get)*
                             }
                         }
-                        Some(entity)
+                        Some((entity, found))
                     },
                     None => None,
                 };
 
                 Ok(entity)
             }
-        }
+        };
     )
 }
 
@@ -244,57 +275,59 @@ fn build_impl_entity(
     });
 
     quote!(
-        use grapefruit::Column;
-        impl grapefruit::Entity for #struct_name {
+        const _: () = {
+            use grapefruit::Column;
+            impl grapefruit::Entity for #struct_name {
 
-            fn table_info() -> grapefruit::TableInfo {
-                grapefruit::TableInfo {
-                    table_name: #table_name.into(),
+                fn table_info() -> grapefruit::TableInfo {
+                    grapefruit::TableInfo {
+                        table_name: #table_name.into(),
+                    }
                 }
-            }
 
-            fn columns() -> std::collections::HashMap<String, grapefruit::ColumnInfo> {
-                let mut map = std::collections::HashMap::new();
-                #(#column_map_info)*
-                map
-            }
+                fn columns() -> std::collections::HashMap<String, grapefruit::ColumnInfo> {
+                    let mut map = std::collections::HashMap::new();
+                    #(#column_map_info)*
+                    map
+                }
 
-            fn to_value(&self) -> std::collections::HashMap<String, grapefruit::Value> {
-                let mut map = std::collections::HashMap::new();
-                #(#to_value)*
-                map
-            }
+                fn to_value(&self) -> std::collections::HashMap<String, grapefruit::Value> {
+                    let mut map = std::collections::HashMap::new();
+                    #(#to_value)*
+                    map
+                }
 
-            fn primary_key() -> Option<grapefruit::ColumnInfo> {
-                #table_id_info
-            }
+                fn primary_key() -> Option<grapefruit::ColumnInfo> {
+                    #table_id_info
+                }
 
-            fn logic_delete() -> Option<grapefruit::ColumnInfo> {
-                #logic_delete_info
-            }
+                fn logic_delete() -> Option<grapefruit::ColumnInfo> {
+                    #logic_delete_info
+                }
 
-            fn version() -> Option<grapefruit::ColumnInfo> {
-                #version_info
-            }
+                fn version() -> Option<grapefruit::ColumnInfo> {
+                    #version_info
+                }
 
-            fn insert_columns() -> Vec<String> {
-                vec![
-                    #(#insert_column_info.to_string()),*
-                ]
-            }
+                fn insert_columns() -> Vec<String> {
+                    vec![
+                        #(#insert_column_info.to_string()),*
+                    ]
+                }
 
-            fn update_columns() -> Vec<String>{
-                vec![
-                    #(#update_column_info.to_string()),*
-                ]
-            }
+                fn update_columns() -> Vec<String>{
+                    vec![
+                        #(#update_column_info.to_string()),*
+                    ]
+                }
 
-            fn select_columns() -> Vec<String>{
-                vec![
-                    #(#select_column_info.to_string()),*
-                ]
+                fn select_columns() -> Vec<String>{
+                    vec![
+                        #(#select_column_info.to_string()),*
+                    ]
+                }
             }
-        }
+        };
     )
 }
 
@@ -312,7 +345,8 @@ fn get_column_info(fields: &[ColumnInformation]) -> HashMap<String, proc_macro2:
         let mut column_type = quote!(grapefruit::ColumnType::TableColumn(#field_type.to_string()));
         let mut fill = quote!(grapefruit::Fill::Default);
         let mut logic_delete = false;
-        let mut version = false;
+        let mut version = quote!(None);
+        let mut nullable = field_type.starts_with("Option<");
 
         match attribute {
             ColumnAttribute::TableId(table_id) => {
@@ -322,6 +356,7 @@ fn get_column_info(fields: &[ColumnInformation]) -> HashMap<String, proc_macro2:
                 column_type =
                     quote!(grapefruit::ColumnType::TableId(grapefruit::IdType::from_str(#id_type)));
                 fill = quote!(grapefruit::Fill::Insert);
+                nullable = false;
             }
             ColumnAttribute::TableColumn(table_field) => {
                 ignore = table_field.ignore;
@@ -332,7 +367,10 @@ fn get_column_info(fields: &[ColumnInformation]) -> HashMap<String, proc_macro2:
                 update_strateg = quote!(grapefruit::ColumnStrategy::from_str(#update_strateg_str));
                 fill = quote!(grapefruit::Fill::from_str(#fill_str));
                 logic_delete = table_field.is_logic_delete;
-                version = table_field.version;
+                if table_field.version {
+                    let ty = &f.field.ty;
+                    version = quote!(Some(<#ty as grapefruit::ValueType>::array_type()));
+                }
             }
             _ => {}
         }
@@ -347,6 +385,7 @@ fn get_column_info(fields: &[ColumnInformation]) -> HashMap<String, proc_macro2:
                 column_type: #column_type,
                 fill: #fill,
                 is_logic_delete: #logic_delete,
+                nullable: #nullable,
                 version: #version,
             },
         );
@@ -0,0 +1,69 @@
+use crate::Column;
+
+/// Aggregate function selected by `select_aggregate` and `select_grouped`
+#[derive(Clone, Debug, PartialEq)]
+pub enum Aggregate {
+    Count,
+    /// `COUNT(column)`, which skips the rows where column is `NULL`
+    CountColumn(String),
+    CountDistinct(String),
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+}
+
+impl Aggregate {
+    pub fn count() -> Self {
+        Aggregate::Count
+    }
+
+    pub fn count_column<C: Column>(column: C) -> Self {
+        Aggregate::CountColumn(column.alias_unwrap())
+    }
+
+    pub fn count_distinct<C: Column>(column: C) -> Self {
+        Aggregate::CountDistinct(column.alias_unwrap())
+    }
+
+    pub fn sum<C: Column>(column: C) -> Self {
+        Aggregate::Sum(column.alias_unwrap())
+    }
+
+    pub fn avg<C: Column>(column: C) -> Self {
+        Aggregate::Avg(column.alias_unwrap())
+    }
+
+    pub fn min<C: Column>(column: C) -> Self {
+        Aggregate::Min(column.alias_unwrap())
+    }
+
+    pub fn max<C: Column>(column: C) -> Self {
+        Aggregate::Max(column.alias_unwrap())
+    }
+
+    pub fn sql(&self) -> String {
+        match self {
+            Aggregate::Count => "COUNT(1)".to_string(),
+            Aggregate::CountColumn(column) => format!("COUNT({})", column),
+            Aggregate::CountDistinct(column) => format!("COUNT(DISTINCT {})", column),
+            Aggregate::Sum(column) => format!("SUM({})", column),
+            Aggregate::Avg(column) => format!("AVG({})", column),
+            Aggregate::Min(column) => format!("MIN({})", column),
+            Aggregate::Max(column) => format!("MAX({})", column),
+        }
+    }
+
+    /// Name of the result column, e.g. `sum_amount`
+    pub fn name(&self) -> String {
+        match self {
+            Aggregate::Count => "count".to_string(),
+            Aggregate::CountColumn(column) => format!("count_{}", column),
+            Aggregate::CountDistinct(column) => format!("count_distinct_{}", column),
+            Aggregate::Sum(column) => format!("sum_{}", column),
+            Aggregate::Avg(column) => format!("avg_{}", column),
+            Aggregate::Min(column) => format!("min_{}", column),
+            Aggregate::Max(column) => format!("max_{}", column),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use futures::{Stream, StreamExt};
+use sqlx::postgres::{PgConnection, PgCopyIn};
+use std::ops::DerefMut;
+
+use crate::transaction::transaction_finished;
+use crate::{
+    insert_value, Entity, Grapefruit, GrapefruitError, GrapefruitResult, PlatformPool,
+    PlatformTransaction, Value,
+};
+
+/// Bytes buffered before a chunk is sent to the server
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+impl Grapefruit {
+    /// Bulk load `entities` through `COPY ... FROM STDIN`.
+    ///
+    /// Rows are always sent in the CSV format; the text and binary formats
+    /// are not supported. Columns follow `Entity::insert_columns`, with id
+    /// generation and insert fill applied as `insert` does. Every row carries
+    /// every column, so a `not_null` strategy cannot leave a column out and
+    /// `None` is sent as `NULL`. Only available on Postgres; runs on the
+    /// current transaction when there is one. Returns the number of rows
+    /// copied.
+    pub async fn copy_in<S>(&self, entities: S) -> GrapefruitResult<u64>
+    where
+        S: Stream + Send,
+        S::Item: Entity,
+    {
+        let sql = format!(
+            "COPY {} ({}) FROM STDIN WITH (FORMAT csv)",
+            S::Item::table_name(),
+            copy_columns::<S::Item>().join(",")
+        );
+
+        let Some(context) = &self.transaction else {
+            #[allow(unreachable_patterns)]
+            return match self.pool() {
+                PlatformPool::Postgres(pool) => {
+                    let mut conn = pool.acquire().await?;
+                    let copy = conn.copy_in_raw(&sql).await?;
+                    self.send_rows(copy, entities).await
+                }
+                _ => Err(copy_unsupported()),
+            };
+        };
+        #[allow(unreachable_patterns)]
+        match context.transaction.lock().await.as_mut() {
+            Some(PlatformTransaction::Postgres(tx)) => {
+                let copy = tx.copy_in_raw(&sql).await?;
+                self.send_rows(copy, entities).await
+            }
+            Some(_) => Err(copy_unsupported()),
+            None => Err(transaction_finished()),
+        }
+    }
+
+    async fn send_rows<S, C>(&self, mut copy: PgCopyIn<C>, entities: S) -> GrapefruitResult<u64>
+    where
+        S: Stream + Send,
+        S::Item: Entity,
+        C: DerefMut<Target = PgConnection>,
+    {
+        let columns = copy_columns::<S::Item>();
+        let column_map = S::Item::columns();
+        let mut buffer = String::with_capacity(COPY_BUFFER_SIZE);
+        let mut entities = std::pin::pin!(entities);
+        while let Some(entity) = entities.next().await {
+            let data = entity.to_value();
+            for (i, column) in columns.iter().enumerate() {
+                let column_info = column_map.get(column).unwrap();
+                let value = data
+                    .get(column)
+                    .unwrap_or_else(|| panic!("{} column value not found.", column));
+                let value = match insert_value(column_info, value, self, false).await {
+                    Ok(value) => value,
+                    Err(e) => {
+                        copy.abort(e.to_string()).await?;
+                        return Err(e);
+                    }
+                };
+                if i > 0 {
+                    buffer.push(',');
+                }
+                write_csv_field(&mut buffer, value.as_ref());
+            }
+            buffer.push('\n');
+            if buffer.len() >= COPY_BUFFER_SIZE {
+                copy.send(buffer.as_bytes()).await?;
+                buffer.clear();
+            }
+        }
+        if !buffer.is_empty() {
+            copy.send(buffer.as_bytes()).await?;
+        }
+        Ok(copy.finish().await?)
+    }
+}
+
+/// Columns written by `copy_in`; ids assigned by the database are left out
+fn copy_columns<T>() -> Vec<String>
+where
+    T: Entity,
+{
+    let column_map = T::columns();
+    T::insert_columns()
+        .into_iter()
+        .filter(|column| {
+            let column_info = column_map.get(column).unwrap();
+            !(column_info.is_table_id() && column_info.id_type() == crate::IdType::Auto)
+        })
+        .collect()
+}
+
+fn copy_unsupported() -> GrapefruitError {
+    GrapefruitError::PlatformError("COPY is only supported on Postgres".to_string())
+}
+
+/// Append `value` as a CSV field; an unquoted empty field is `NULL`
+fn write_csv_field(buffer: &mut String, value: Option<&Value>) {
+    let Some(text) = value.and_then(copy_text) else {
+        return;
+    };
+    buffer.push('"');
+    buffer.push_str(&text.replace('"', "\"\""));
+    buffer.push('"');
+}
+
+/// Postgres text representation of `value`, `None` for `NULL`
+fn copy_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Bool(v) => v.map(|v| if v { "t" } else { "f" }.to_string()),
+        Value::Tinyint(v) => v.map(|v| v.to_string()),
+        Value::Smallint(v) => v.map(|v| v.to_string()),
+        Value::Int(v) => v.map(|v| v.to_string()),
+        Value::Bigint(v) => v.map(|v| v.to_string()),
+        Value::TinyUnsigned(v) => v.map(|v| v.to_string()),
+        Value::SmallUnsigned(v) => v.map(|v| v.to_string()),
+        Value::Unsigned(v) => v.map(|v| v.to_string()),
+        Value::BigUnsigned(v) => v.map(|v| v.to_string()),
+        Value::Float(v) => v.map(|v| float_text(v as f64)),
+        Value::Double(v) => v.map(float_text),
+        Value::BigDecimal(v) => v.as_ref().map(|v| v.to_string()),
+        Value::Char(v) => v.map(|v| v.to_string()),
+        Value::String(v) => v.clone(),
+        Value::Bytes(v) => v.as_ref().map(|v| {
+            let hex = v.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+            format!("\\x{}", hex)
+        }),
+        Value::Json(v) => v.as_ref().map(|v| v.to_string()),
+        Value::ChronoDate(v) => v.as_ref().map(|v| v.to_string()),
+        Value::ChronoTime(v) => v.as_ref().map(|v| v.to_string()),
+        Value::ChronoDateTime(v) => v.as_ref().map(|v| v.to_string()),
+        Value::ChronoDateTimeUtc(v) => v.as_ref().map(|v| v.to_rfc3339()),
+        Value::ChronoDateTimeLocal(v) => v.as_ref().map(|v| v.to_rfc3339()),
+        Value::ChronoDateTimeWithTimeZone(v) => v.as_ref().map(|v| v.to_rfc3339()),
+        Value::Array(_, v) => match v {
+            Some(values) => {
+                let mut elements = Vec::with_capacity(values.len());
+                for value in values.iter() {
+                    elements.push(match copy_text(value) {
+                        Some(text) => {
+                            format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+                        }
+                        None => "NULL".to_string(),
+                    });
+                }
+                Some(format!("{{{}}}", elements.join(",")))
+            }
+            None => None,
+        },
+    }
+}
+
+fn float_text(v: f64) -> String {
+    match v {
+        v if v == f64::INFINITY => "Infinity".to_string(),
+        v if v == f64::NEG_INFINITY => "-Infinity".to_string(),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ArrayType;
+
+    fn field(value: Value) -> String {
+        let mut buffer = String::new();
+        write_csv_field(&mut buffer, Some(&value));
+        buffer
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        assert_eq!(field("plain".into()), "\"plain\"");
+        assert_eq!(field("a,b".into()), "\"a,b\"");
+        assert_eq!(field("say \"hi\"".into()), "\"say \"\"hi\"\"\"");
+        assert_eq!(field("two\nlines\r\n".into()), "\"two\nlines\r\n\"");
+        assert_eq!(field(42i64.into()), "\"42\"");
+        assert_eq!(field(true.into()), "\"t\"");
+    }
+
+    #[test]
+    fn test_csv_null_and_empty() {
+        let mut buffer = String::new();
+        write_csv_field(&mut buffer, None);
+        assert_eq!(buffer, "");
+        assert_eq!(field(Value::String(None)), "");
+        assert_eq!(field(Value::Bigint(None)), "");
+        // an empty string is quoted, so it does not read back as NULL
+        assert_eq!(field("".into()), "\"\"");
+    }
+
+    #[test]
+    fn test_copy_text_bytes_and_arrays() {
+        assert_eq!(
+            copy_text(&vec![0u8, 0xab, 0xff].into()),
+            Some("\\x00abff".to_string())
+        );
+        assert_eq!(
+            copy_text(&vec![1i32, 2, 3].into()),
+            Some("{\"1\",\"2\",\"3\"}".to_string())
+        );
+        let strings: Value = vec!["a\"b".to_string(), "c\\d".to_string()].into();
+        assert_eq!(
+            copy_text(&strings),
+            Some("{\"a\\\"b\",\"c\\\\d\"}".to_string())
+        );
+        let with_null = Value::Array(
+            ArrayType::String,
+            Some(Box::new(vec!["a".into(), Value::String(None)])),
+        );
+        assert_eq!(copy_text(&with_null), Some("{\"a\",NULL}".to_string()));
+        assert_eq!(copy_text(&Value::Array(ArrayType::Int, None)), None);
+        // the array text is quoted once more as a CSV field
+        assert_eq!(field(strings), "\"{\"\"a\\\"\"b\"\",\"\"c\\\\d\"\"}\"");
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    Entity, GrapefruitError, GrapefruitResult, OrderByType, Segment, SegmentType, Value, Wrapper,
+};
+
+/// Position in a keyset-paginated result, taken from the ordering columns
+/// of the first or last record of a page.
+///
+/// The fields are private; pass the cursor around as is, serialized with
+/// serde or as the string of `encode`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Cursor {
+    columns: Vec<String>,
+    values: Vec<Value>,
+    /// read the page before the position instead of after it
+    backward: bool,
+}
+
+impl Cursor {
+    fn from_record<T>(record: &T, keys: &[(String, OrderByType)], backward: bool) -> Self
+    where
+        T: Entity,
+    {
+        let column_map = T::columns();
+        let mut values = record.to_value();
+        let values = keys
+            .iter()
+            .map(|(column, _)| {
+                column_map
+                    .values()
+                    .find(|c| &c.alias == column)
+                    .and_then(|c| values.remove(&c.name))
+                    .expect("cursor column is not an entity column")
+            })
+            .collect();
+        Self {
+            columns: keys.iter().map(|(column, _)| column.clone()).collect(),
+            values,
+            backward,
+        }
+    }
+
+    /// URL safe token of the cursor
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("cursor is always serializable");
+        json.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Cursor from a token made by `encode`
+    pub fn decode(token: &str) -> GrapefruitResult<Self> {
+        let invalid = || GrapefruitError::ObjectValidError(format!("invalid cursor `{}`", token));
+        let json = (0..token.len())
+            .step_by(2)
+            .map(|i| {
+                token
+                    .get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+/// Page read by `cursor_page`
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CursorPage<T>
+where
+    T: Sized,
+{
+    pub records: Vec<T>,
+    /// cursor of the following page, `None` on the last one
+    pub next: Option<Cursor>,
+    /// cursor of the preceding page, `None` on the first one
+    pub previous: Option<Cursor>,
+    /// rows matching the wrapper, when counted
+    pub total: Option<i64>,
+}
+
+impl<T> CursorPage<T>
+where
+    T: Entity,
+{
+    /// Page of `size` out of `records`, read with one extra row to tell
+    /// whether there is more in the direction of `cursor`
+    pub(crate) fn new(
+        mut records: Vec<T>,
+        keys: &[(String, OrderByType)],
+        size: usize,
+        cursor: Option<&Cursor>,
+        total: Option<i64>,
+    ) -> Self {
+        let more = records.len() > size;
+        records.truncate(size);
+        let backward = cursor.is_some_and(|c| c.backward);
+        if backward {
+            records.reverse();
+        }
+        // going forward there is a previous page whenever we came from a
+        // cursor, going backward there is a next one
+        let (has_previous, has_next) = match backward {
+            true => (more, true),
+            false => (cursor.is_some(), more),
+        };
+        let previous = records
+            .first()
+            .filter(|_| has_previous)
+            .map(|record| Cursor::from_record(record, keys, true));
+        let next = records
+            .last()
+            .filter(|_| has_next)
+            .map(|record| Cursor::from_record(record, keys, false));
+        Self {
+            records,
+            next,
+            previous,
+            total,
+        }
+    }
+}
+
+/// Columns ordering a keyset page: the wrapper's `order_by` followed by the
+/// primary key, which makes the order total
+pub(crate) fn cursor_keys<T>(wrapper: &Wrapper) -> GrapefruitResult<Vec<(String, OrderByType)>>
+where
+    T: Entity,
+{
+    let Some(primary_key) = T::primary_key() else {
+        return Err(GrapefruitError::PrimaryKeyNone(T::table_name()));
+    };
+    let mut keys = wrapper
+        .expression
+        .order_by
+        .get_order_by()
+        .unwrap_or_default();
+    if !keys.iter().any(|(column, _)| column == &primary_key.alias) {
+        keys.push((primary_key.alias, OrderByType::Asc));
+    }
+    let column_map = T::columns();
+    for (column, _) in keys.iter() {
+        let Some(info) = column_map.values().find(|c| &c.alias == column) else {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "cursor can not order by `{}`, which is not a column of {}",
+                column,
+                T::table_name()
+            )));
+        };
+        let excluded =
+            wrapper.sql_exclude.contains(&info.name) || wrapper.sql_exclude.contains(&info.alias);
+        if wrapper.sql_select.is_some() || excluded {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "cursor column `{}` must be selected",
+                column
+            )));
+        }
+        // a NULL in the boundary row would leave no value to seek past
+        if info.nullable {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "cursor can not order by `{}`, which may be NULL",
+                column
+            )));
+        }
+    }
+    Ok(keys)
+}
+
+/// `wrapper` narrowed to the rows past `cursor` and ordered by `keys`,
+/// in reverse when reading backward
+pub(crate) fn seek_wrapper(
+    wrapper: &Wrapper,
+    keys: &[(String, OrderByType)],
+    cursor: Option<&Cursor>,
+) -> GrapefruitResult<Wrapper> {
+    let backward = cursor.is_some_and(|c| c.backward);
+    let mut wrapper = wrapper.clone();
+    let order_by = keys
+        .iter()
+        .map(|(column, order)| {
+            let order = match (order, backward) {
+                (OrderByType::Asc, true) => OrderByType::Desc,
+                (OrderByType::Desc, true) => OrderByType::Asc,
+                (order, false) => order.clone(),
+            };
+            (column.clone(), order)
+        })
+        .collect();
+    wrapper.expression.order_by = Segment::OrderBy(order_by);
+    let Some(cursor) = cursor else {
+        return Ok(wrapper);
+    };
+    let columns = keys.iter().map(|(column, _)| column).collect::<Vec<_>>();
+    if cursor.columns.iter().collect::<Vec<_>>() != columns {
+        return Err(GrapefruitError::ObjectValidError(format!(
+            "cursor of ({}) does not match the order ({})",
+            cursor.columns.join(", "),
+            keys.iter()
+                .map(|(c, _)| c.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    if let Some(index) = cursor.values.iter().position(|value| value.is_none()) {
+        return Err(GrapefruitError::ObjectValidError(format!(
+            "cursor column `{}` is NULL",
+            cursor.columns[index]
+        )));
+    }
+
+    // (k0 > v0) or (k0 = v0 and k1 > v1) or ..., with `<` for the columns
+    // read in descending order. Negative bracket numbers keep the param
+    // names clear of the wrapper's own.
+    let mut seek = Wrapper::with_bracket_num(-2);
+    for (index, ((column, order), value)) in keys.iter().zip(cursor.values.iter()).enumerate() {
+        let mut branch = Wrapper::with_bracket_num(-3 - index as i32);
+        for ((column, _), value) in keys[..index].iter().zip(cursor.values.iter()) {
+            let segment = Segment::Eq(column.clone(), value.clone());
+            branch = branch.do_it(true, SegmentType::Normal, segment);
+        }
+        let segment = match (order == &OrderByType::Asc) != backward {
+            true => Segment::Gt(column.clone(), value.clone()),
+            false => Segment::Lt(column.clone(), value.clone()),
+        };
+        branch = branch.do_it(true, SegmentType::Normal, segment);
+        if index != 0 {
+            seek = seek.or();
+        }
+        seek = seek.do_it(
+            true,
+            SegmentType::Normal,
+            Segment::Bracket(Box::new(branch)),
+        );
+    }
+
+    let normal = std::mem::take(&mut wrapper.expression.normal);
+    if !normal.is_empty() {
+        let mut conditions = Wrapper::with_bracket_num(wrapper.bracket_num);
+        conditions.expression.normal = normal;
+        wrapper
+            .expression
+            .normal
+            .push(Segment::Bracket(Box::new(conditions)));
+    }
+    wrapper
+        .expression
+        .normal
+        .push(Segment::Bracket(Box::new(seek)));
+    Ok(wrapper)
+}
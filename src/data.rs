@@ -1,6 +1,11 @@
+use std::collections::HashMap;
 use std::fmt;
 
-use crate::GrapefruitResult;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use sqlx::ValueRef;
+use sqlx::{Column, Row, TypeInfo};
+
+use crate::{GrapefruitResult, Value};
 
 #[derive(Debug)]
 pub struct ExecResult {
@@ -92,6 +97,14 @@ impl QueryResults {
         }
         Ok(values)
     }
+
+    /// Every row as a map of column name to value
+    pub fn try_get_maps(&self) -> GrapefruitResult<Vec<HashMap<String, Value>>> {
+        self.results
+            .iter()
+            .map(|result| result.try_get_map())
+            .collect()
+    }
 }
 
 #[derive(Debug)]
@@ -106,6 +119,26 @@ impl QueryResult {
     {
         T::try_get(self)
     }
+
+    /// Column `index` decoded by its database type, `None` without a row
+    pub fn try_get_value(&self, index: usize) -> GrapefruitResult<Option<Value>> {
+        let value = match self.row.as_ref() {
+            Some(row) => Some(row.try_get_value(index)?),
+            None => None,
+        };
+        Ok(value)
+    }
+
+    /// Every column by name, decoded by its database type
+    pub fn try_get_map(&self) -> GrapefruitResult<HashMap<String, Value>> {
+        let mut map = HashMap::new();
+        if let Some(row) = self.row.as_ref() {
+            for (index, name) in row.column_names().into_iter().enumerate() {
+                map.insert(name, row.try_get_value(index)?);
+            }
+        }
+        Ok(map)
+    }
 }
 
 #[cfg(feature = "postgres")]
@@ -210,6 +243,180 @@ pub enum QueryRow {
     Sqlite(sqlx::sqlite::SqliteRow),
 }
 
+/// Decode column `$index` of `$row` as `Option<$type>` into a `Value`
+macro_rules! decode_value {
+    ( $row: expr, $index: expr, $type: ty ) => {
+        $row.try_get::<Option<$type>, _>($index)?.into()
+    };
+}
+
+/// Decode column `$index` of `$row`, whatever its type, into its raw text,
+/// or its raw bytes when they are not UTF-8
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+macro_rules! decode_raw {
+    ( $row: expr, $index: expr ) => {
+        match $row.try_get_unchecked::<Option<Vec<u8>>, _>($index)? {
+            Some(bytes) => match String::from_utf8(bytes) {
+                Ok(text) => Some(text).into(),
+                Err(e) => Some(e.into_bytes()).into(),
+            },
+            None => Value::String(None),
+        }
+    };
+}
+
+impl QueryRow {
+    fn column_names(&self) -> Vec<String> {
+        match self {
+            #[cfg(feature = "mysql")]
+            QueryRow::MySql(row) => row.columns().iter().map(|c| c.name().to_string()).collect(),
+            #[cfg(feature = "postgres")]
+            QueryRow::Postgres(row) => row.columns().iter().map(|c| c.name().to_string()).collect(),
+            #[cfg(feature = "sqlite")]
+            QueryRow::Sqlite(row) => row.columns().iter().map(|c| c.name().to_string()).collect(),
+        }
+    }
+
+    fn try_get_value(&self, index: usize) -> GrapefruitResult<Value> {
+        let value = match self {
+            #[cfg(feature = "mysql")]
+            QueryRow::MySql(row) => {
+                let type_name = row.column(index).type_info().name().to_string();
+                match type_name.as_str() {
+                    "BOOLEAN" => decode_value!(row, index, bool),
+                    "TINYINT" => decode_value!(row, index, i8),
+                    "SMALLINT" => decode_value!(row, index, i16),
+                    "INT" | "MEDIUMINT" => decode_value!(row, index, i32),
+                    "BIGINT" => decode_value!(row, index, i64),
+                    "TINYINT UNSIGNED" => decode_value!(row, index, u8),
+                    "SMALLINT UNSIGNED" => decode_value!(row, index, u16),
+                    "INT UNSIGNED" | "MEDIUMINT UNSIGNED" | "YEAR" => {
+                        decode_value!(row, index, u32)
+                    }
+                    "BIGINT UNSIGNED" => decode_value!(row, index, u64),
+                    "FLOAT" => decode_value!(row, index, f32),
+                    "DOUBLE" => decode_value!(row, index, f64),
+                    "DECIMAL" => decode_value!(row, index, sqlx::types::BigDecimal),
+                    "JSON" => decode_value!(row, index, serde_json::Value),
+                    "DATE" => decode_value!(row, index, sqlx::types::chrono::NaiveDate),
+                    "TIME" => decode_value!(row, index, sqlx::types::chrono::NaiveTime),
+                    "DATETIME" => decode_value!(row, index, sqlx::types::chrono::NaiveDateTime),
+                    "TIMESTAMP" => decode_value!(
+                        row,
+                        index,
+                        sqlx::types::chrono::DateTime<sqlx::types::chrono::Utc>
+                    ),
+                    t if t.contains("BLOB") || t.contains("BINARY") => {
+                        decode_value!(row, index, Vec<u8>)
+                    }
+                    _ => decode_raw!(row, index),
+                }
+            }
+            #[cfg(feature = "postgres")]
+            QueryRow::Postgres(row) => {
+                let type_name = row.column(index).type_info().name().to_string();
+                match type_name.as_str() {
+                    "BOOL" => decode_value!(row, index, bool),
+                    "\"CHAR\"" => decode_value!(row, index, i8),
+                    "INT2" => decode_value!(row, index, i16),
+                    "INT4" => decode_value!(row, index, i32),
+                    "INT8" => decode_value!(row, index, i64),
+                    "FLOAT4" => decode_value!(row, index, f32),
+                    "FLOAT8" => decode_value!(row, index, f64),
+                    "NUMERIC" => decode_value!(row, index, sqlx::types::BigDecimal),
+                    "TEXT" | "VARCHAR" | "BPCHAR" | "NAME" => decode_value!(row, index, String),
+                    "UUID" => row
+                        .try_get::<Option<sqlx::types::Uuid>, _>(index)?
+                        .map(|uuid| uuid.to_string())
+                        .into(),
+                    "BYTEA" => decode_value!(row, index, Vec<u8>),
+                    "JSON" | "JSONB" => decode_value!(row, index, serde_json::Value),
+                    "DATE" => decode_value!(row, index, sqlx::types::chrono::NaiveDate),
+                    "TIME" => decode_value!(row, index, sqlx::types::chrono::NaiveTime),
+                    "TIMESTAMP" => decode_value!(row, index, sqlx::types::chrono::NaiveDateTime),
+                    "TIMESTAMPTZ" => decode_value!(
+                        row,
+                        index,
+                        sqlx::types::chrono::DateTime<sqlx::types::chrono::Utc>
+                    ),
+                    "BOOL[]" => decode_value!(row, index, Vec<bool>),
+                    "\"CHAR\"[]" => decode_value!(row, index, Vec<i8>),
+                    "INT2[]" => decode_value!(row, index, Vec<i16>),
+                    "INT4[]" => decode_value!(row, index, Vec<i32>),
+                    "INT8[]" => decode_value!(row, index, Vec<i64>),
+                    "FLOAT4[]" => decode_value!(row, index, Vec<f32>),
+                    "FLOAT8[]" => decode_value!(row, index, Vec<f64>),
+                    "NUMERIC[]" => decode_value!(row, index, Vec<sqlx::types::BigDecimal>),
+                    "TEXT[]" | "VARCHAR[]" | "BPCHAR[]" | "NAME[]" => {
+                        decode_value!(row, index, Vec<String>)
+                    }
+                    "UUID[]" => row
+                        .try_get::<Option<Vec<sqlx::types::Uuid>>, _>(index)?
+                        .map(|uuids| {
+                            uuids
+                                .iter()
+                                .map(|uuid| uuid.to_string())
+                                .collect::<Vec<_>>()
+                        })
+                        .into(),
+                    "BYTEA[]" => decode_value!(row, index, Vec<Vec<u8>>),
+                    "JSON[]" | "JSONB[]" => decode_value!(row, index, Vec<serde_json::Value>),
+                    "DATE[]" => decode_value!(row, index, Vec<sqlx::types::chrono::NaiveDate>),
+                    "TIME[]" => decode_value!(row, index, Vec<sqlx::types::chrono::NaiveTime>),
+                    "TIMESTAMP[]" => {
+                        decode_value!(row, index, Vec<sqlx::types::chrono::NaiveDateTime>)
+                    }
+                    "TIMESTAMPTZ[]" => decode_value!(
+                        row,
+                        index,
+                        Vec<sqlx::types::chrono::DateTime<sqlx::types::chrono::Utc>>
+                    ),
+                    // INET, INTERVAL and the like have no `Value` of their own
+                    _ => {
+                        let value = row.try_get_raw(index)?;
+                        if value.is_null() {
+                            Value::String(None)
+                        } else {
+                            match value.format() {
+                                sqlx::postgres::PgValueFormat::Text => {
+                                    Some(value.as_str().map_err(sqlx::Error::Decode)?.to_string())
+                                        .into()
+                                }
+                                sqlx::postgres::PgValueFormat::Binary => {
+                                    Some(value.as_bytes().map_err(sqlx::Error::Decode)?.to_vec())
+                                        .into()
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            QueryRow::Sqlite(row) => {
+                // expressions and NUMERIC columns have no declared type of
+                // their own, fall back to the stored one
+                let declared = row.column(index).type_info();
+                let type_name = if declared.is_null() || declared.name() == "NUMERIC" {
+                    row.try_get_raw(index)?.type_info().name().to_string()
+                } else {
+                    declared.name().to_string()
+                };
+                match type_name.as_str() {
+                    "BOOLEAN" => decode_value!(row, index, bool),
+                    "INTEGER" => decode_value!(row, index, i64),
+                    "REAL" => decode_value!(row, index, f64),
+                    "BLOB" => decode_value!(row, index, Vec<u8>),
+                    "DATE" => decode_value!(row, index, sqlx::types::chrono::NaiveDate),
+                    "TIME" => decode_value!(row, index, sqlx::types::chrono::NaiveTime),
+                    "DATETIME" => decode_value!(row, index, sqlx::types::chrono::NaiveDateTime),
+                    _ => decode_raw!(row, index),
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
 #[allow(unused_variables)]
 impl fmt::Debug for QueryRow {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
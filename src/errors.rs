@@ -37,6 +37,29 @@ pub enum GrapefruitError {
     #[error("PlatformError: `{0}`")]
     PlatformError(String),
 
+    #[error("OptimisticLockConflict: `{0}` was modified concurrently")]
+    OptimisticLockConflict(String),
+
+    #[error("TransactionError: `{0}`")]
+    TransactionError(String),
+
     #[error("ValueTypeError: Value type mismatch")]
     ValueTypeError(),
 }
+
+impl GrapefruitError {
+    /// Whether the error is a serialization failure or deadlock,
+    /// after which the whole transaction can safely be run again
+    pub fn is_retryable(&self) -> bool {
+        let GrapefruitError::SqlError(sqlx::Error::Database(e)) = self else {
+            return false;
+        };
+        #[cfg(feature = "mysql")]
+        if let Some(e) = e.try_downcast_ref::<sqlx::mysql::MySqlDatabaseError>() {
+            // ER_LOCK_DEADLOCK
+            return e.number() == 1213;
+        }
+        // serialization_failure, deadlock_detected
+        matches!(e.code().as_deref(), Some("40001") | Some("40P01"))
+    }
+}
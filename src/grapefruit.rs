@@ -1,13 +1,23 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use futures::stream::{self, BoxStream, Stream, StreamExt};
 use once_cell::sync::{Lazy, OnceCell};
 
+use crate::transaction::{transaction_finished, TransactionContext};
 use crate::{
-    build_delete_sql, build_insert_sql, build_logic_delete, build_select_sql, build_update_sql,
-    Column, GrapefruitError, GrapefruitOptions, GrapefruitRepository, GrapefruitResult, Page,
-    Platform, PlatformPool, Value, Wrapper,
+    build_aggregate_sql, build_delete_sql, build_delete_wrapper_sql, build_insert_one_sql,
+    build_insert_sql, build_logic_delete, build_named_sql, build_select_sql,
+    build_select_wrapper_sql, build_update_set_sql, build_update_sql, build_upsert_sql,
+    cursor_keys, seek_wrapper, select_columns, version_value, Aggregate, Column, Cursor,
+    CursorPage, ExecResult, GrapefruitError, GrapefruitOptions, GrapefruitRepository,
+    GrapefruitResult, GrapefruitTx, JoinWrapper, Page, PageRequest, Params, Platform, PlatformPool,
+    QueryResult, QueryResults, RowSender, Segment, TransactionOptions, Value, Wrapper,
 };
 
+/// Rows `select_stream` reads ahead of its consumer
+const STREAM_BUFFER_SIZE: usize = 64;
+
 pub static GRAPEFRUIT: Lazy<OnceCell<Grapefruit>> = Lazy::new(|| OnceCell::new());
 
 #[derive(Clone)]
@@ -15,6 +25,7 @@ pub struct Grapefruit {
     pub(crate) pool: Arc<OnceCell<PlatformPool>>,
     pub(crate) options: GrapefruitOptions,
     pub(crate) platform: Platform,
+    pub(crate) transaction: Option<TransactionContext>,
 }
 
 impl Grapefruit {
@@ -23,6 +34,7 @@ impl Grapefruit {
             pool: Arc::new(OnceCell::new()),
             options: config.clone(),
             platform: config.platform.clone(),
+            transaction: None,
         }
     }
 
@@ -52,6 +64,155 @@ impl Grapefruit {
     pub fn get_update_fill(&self, col: &str) -> Result<Value, GrapefruitError> {
         self.options.meta_object.try_get_update_fill(col)
     }
+
+    /// Begin a transaction, or a savepoint when already inside one
+    pub async fn begin(&self) -> GrapefruitResult<GrapefruitTx> {
+        self.begin_with(&TransactionOptions::default()).await
+    }
+
+    /// Begin a transaction configured by `options`
+    pub async fn begin_with(&self, options: &TransactionOptions) -> GrapefruitResult<GrapefruitTx> {
+        if let Some(context) = &self.transaction {
+            return GrapefruitTx::savepoint(self, context, options).await;
+        }
+        let transaction = self.pool().begin_with(options).await?;
+        Ok(GrapefruitTx::new(self, transaction))
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    pub(crate) fn with_transaction(&self, context: TransactionContext) -> Grapefruit {
+        let mut grapefruit = self.clone();
+        grapefruit.transaction = Some(context);
+        grapefruit
+    }
+
+    pub(crate) async fn execute(&self, sql: &str, params: Params) -> GrapefruitResult<ExecResult> {
+        let Some(context) = &self.transaction else {
+            return self.pool().execute(sql, params).await;
+        };
+        match context.transaction.lock().await.as_mut() {
+            Some(tx) => tx.execute(sql, params).await,
+            None => Err(transaction_finished()),
+        }
+    }
+
+    pub(crate) async fn fetch_one(
+        &self,
+        sql: &str,
+        params: Params,
+    ) -> GrapefruitResult<QueryResult> {
+        let Some(context) = &self.transaction else {
+            return self.pool().fetch_one(sql, params).await;
+        };
+        match context.transaction.lock().await.as_mut() {
+            Some(tx) => tx.fetch_one(sql, params).await,
+            None => Err(transaction_finished()),
+        }
+    }
+
+    pub(crate) async fn fetch_all(
+        &self,
+        sql: &str,
+        params: Params,
+    ) -> GrapefruitResult<QueryResults> {
+        let Some(context) = &self.transaction else {
+            return self.pool().fetch_all(sql, params).await;
+        };
+        match context.transaction.lock().await.as_mut() {
+            Some(tx) => tx.fetch_all(sql, params).await,
+            None => Err(transaction_finished()),
+        }
+    }
+
+    pub(crate) async fn fetch_rows(
+        &self,
+        sql: &str,
+        params: Params,
+        sender: &RowSender,
+    ) -> GrapefruitResult<()> {
+        let Some(context) = &self.transaction else {
+            return self.pool().fetch_rows(sql, params, sender).await;
+        };
+        match context.transaction.lock().await.as_mut() {
+            Some(tx) => tx.fetch_rows(sql, params, sender).await,
+            None => Err(transaction_finished()),
+        }
+    }
+
+    /// Rows of `sql`, read by a spawned task a few rows ahead of the consumer.
+    /// Dropping the stream stops the task after its current row.
+    pub(crate) fn fetch_stream(
+        &self,
+        sql: String,
+        params: Params,
+    ) -> impl Stream<Item = GrapefruitResult<QueryResult>> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER_SIZE);
+        let grapefruit = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = grapefruit.fetch_rows(&sql, params, &sender).await {
+                let _ = sender.send(Err(e)).await;
+            }
+        });
+        stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|row| (row, receiver))
+        })
+    }
+
+    /// Run `statements` as one unit of work, returning the total rows affected.
+    /// More than one statement is wrapped in a transaction (or a savepoint).
+    /// `check` sees the rows affected by each statement and may reject them.
+    pub(crate) async fn execute_all<F>(
+        &self,
+        statements: Vec<(String, Params)>,
+        check: F,
+    ) -> GrapefruitResult<u64>
+    where
+        F: Fn(usize, u64) -> GrapefruitResult<()> + Send + Sync,
+    {
+        if statements.len() == 1 {
+            let (sql, params) = statements.into_iter().next().unwrap();
+            let rows_affected = self.execute(&sql, params).await?.rows_affected();
+            check(0, rows_affected)?;
+            return Ok(rows_affected);
+        }
+        let tx = self.begin().await?;
+        let mut rows_affected = 0;
+        for (index, (sql, params)) in statements.into_iter().enumerate() {
+            let result = tx.grapefruit().execute(&sql, params).await;
+            match result.and_then(|row| check(index, row.rows_affected()).map(|_| row)) {
+                Ok(row) => rows_affected += row.rows_affected(),
+                Err(e) => {
+                    tx.rollback().await?;
+                    return Err(e);
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok(rows_affected)
+    }
+
+    /// An update guarded by a version that matched no row lost the race
+    /// when `wrapper` still matches rows without the guard
+    async fn check_version_conflict<T>(
+        &self,
+        entity: &T,
+        rows_affected: u64,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        if rows_affected > 0 || version_value::<T>(&entity.to_value()).is_none() {
+            return Ok(rows_affected);
+        }
+        match self.count_by_wrapper::<T>(wrapper).await? {
+            0 => Ok(0),
+            _ => Err(GrapefruitError::OptimisticLockConflict(T::table_name())),
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -60,11 +221,9 @@ impl GrapefruitRepository for Grapefruit {
     where
         T: crate::Entity,
     {
-        let (sql, params) = build_insert_sql(&vec![entity], self).await?;
-
-        let row = self.pool().execute(&sql, params).await?;
+        let statements = build_insert_sql(&vec![entity], self).await?;
 
-        Ok(row.rows_affected())
+        self.execute_all(statements, |_, _| Ok(())).await
     }
 
     async fn insert_batch<T>(&self, entities: &[&T]) -> GrapefruitResult<u64>
@@ -75,11 +234,78 @@ impl GrapefruitRepository for Grapefruit {
             return Err(GrapefruitError::EmptyEntity);
         }
 
-        let (sql, params) = build_insert_sql(entities, self).await?;
+        let statements = build_insert_sql(entities, self).await?;
 
-        let row = self.pool().execute(&sql, params).await?;
+        self.execute_all(statements, |_, _| Ok(())).await
+    }
 
-        Ok(row.rows_affected())
+    async fn insert_returning<T>(&self, entity: &T) -> GrapefruitResult<T>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        let (sql, params, id) = build_insert_one_sql(entity, self).await?;
+        let select_columns = T::select_columns();
+
+        if let Some(returning) = self.platform().returning(&select_columns) {
+            let sql = format!("{} {}", sql, returning);
+            let query_result = self.fetch_one(&sql, params).await?;
+            return query_result
+                .try_get()?
+                .ok_or_else(|| GrapefruitError::NoSuchValueError(T::table_name()));
+        }
+
+        // no RETURNING: read the row back through its primary key
+        let result = self.execute(&sql, params).await?;
+        let Some(id) = id.or_else(|| result.last_insert_id().map(Value::from)) else {
+            return Err(GrapefruitError::PrimaryKeyNone(
+                "Primary key not found after inserting entity".to_string(),
+            ));
+        };
+        let primary_key = T::primary_key().alias()?;
+        let (sql, params) = build_select_sql::<T, _>(self, |index| {
+            let sql = format!("{} = {}", primary_key, self.platform().mark(index + 1));
+            (sql, vec![id.clone()])
+        })
+        .await?;
+        let query_result = self.fetch_one(&sql, params).await?;
+        query_result
+            .try_get()?
+            .ok_or_else(|| GrapefruitError::NoSuchValueError(T::table_name()))
+    }
+
+    async fn upsert<T>(&self, entity: &T) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.upsert_batch_on(&[entity], &[]).await
+    }
+
+    async fn upsert_batch<T>(&self, entities: &[&T]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.upsert_batch_on(entities, &[]).await
+    }
+
+    async fn upsert_batch_on<T>(&self, entities: &[&T], conflict: &[&str]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        if entities.is_empty() {
+            return Err(GrapefruitError::EmptyEntity);
+        }
+
+        let statements = build_upsert_sql(entities, self, conflict).await?;
+        let guarded = statements.iter().map(|s| s.2).collect::<Vec<_>>();
+        let statements = statements.into_iter().map(|(sql, params, _)| (sql, params));
+        // a guarded statement that touched no row skipped its update branch
+        self.execute_all(statements.collect(), |index, rows_affected| {
+            if guarded[index] && rows_affected == 0 {
+                return Err(GrapefruitError::OptimisticLockConflict(T::table_name()));
+            }
+            Ok(())
+        })
+        .await
     }
 
     async fn update_by_id<T>(&self, entity: &T) -> GrapefruitResult<u64>
@@ -95,28 +321,34 @@ impl GrapefruitRepository for Grapefruit {
             ));
         }
 
-        let (sql, values) = build_update_sql(entity, self, |index| {
-            let sql = format!("{} = {}", primary_key, self.platform().mark(index + 1));
-            (sql, vec![id.clone()])
-        })
-        .await?;
-
-        let row = self.pool().execute(&sql, values.into()).await?;
+        let wrapper = Wrapper::new().eq(T::primary_key(), id.clone());
+        let (sql, values) = build_update_sql(entity, self, &wrapper).await?;
 
-        Ok(row.rows_affected())
+        let row = self.execute(&sql, values).await?;
+        self.check_version_conflict(entity, row.rows_affected(), wrapper)
+            .await
     }
 
     async fn update_by_wrapper<T>(&self, entity: &T, wrapper: Wrapper) -> GrapefruitResult<u64>
     where
         T: crate::Entity,
     {
-        let (sql, values) = build_update_sql(entity, self, |index| {
-            wrapper.build(self.platform(), index + 1)
-        })
-        .await?;
+        let (sql, values) = build_update_sql(entity, self, &wrapper).await?;
+
+        let sql = wrapper.with_sql_first_last(&sql);
+        let row = self.execute(&sql, values).await?;
+        self.check_version_conflict(entity, row.rows_affected(), wrapper)
+            .await
+    }
 
-        let row = self.pool().execute(&sql, values.into()).await?;
+    async fn update_by_set<T>(&self, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        let (sql, params) = build_update_set_sql::<T>(self, &wrapper).await?;
 
+        let sql = wrapper.with_sql_first_last(&sql);
+        let row = self.execute(&sql, params).await?;
         Ok(row.rows_affected())
     }
 
@@ -131,7 +363,7 @@ impl GrapefruitRepository for Grapefruit {
             (sql, vec![id.clone().into()])
         })
         .await?;
-        let result = self.pool().execute(&sql, params).await?;
+        let result = self.execute(&sql, params).await?;
         Ok(result.is_success())
     }
 
@@ -152,7 +384,7 @@ impl GrapefruitRepository for Grapefruit {
             (sql, ids.iter().map(|id| id.into()).collect::<Vec<Value>>())
         })
         .await?;
-        let result = self.pool().execute(&sql, params).await?;
+        let result = self.execute(&sql, params).await?;
         Ok(result.is_success())
     }
 
@@ -160,13 +392,56 @@ impl GrapefruitRepository for Grapefruit {
     where
         T: crate::Entity,
     {
-        let (sql, params) =
-            build_delete_sql::<T, _>(self, |index| wrapper.build(self.platform(), index + 1))
-                .await?;
-        let result = self.pool().execute(&sql, params).await?;
+        let (sql, params) = build_delete_wrapper_sql::<T>(self, &wrapper).await?;
+        let sql = wrapper.with_sql_first_last(&sql);
+        let result = self.execute(&sql, params).await?;
         Ok(result.is_success())
     }
 
+    async fn force_delete_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey,
+    {
+        let primary_key = T::primary_key().alias()?;
+        let sql = format!(
+            "DELETE FROM {} WHERE {} = {}",
+            T::table_name(),
+            primary_key,
+            self.platform().mark(1)
+        );
+        let result = self.execute(&sql, vec![id.into()].into()).await?;
+        Ok(result.is_success())
+    }
+
+    async fn restore_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey,
+    {
+        let Some(primary_key) = T::primary_key() else {
+            return Err(GrapefruitError::PrimaryKeyNone(T::table_name()));
+        };
+        let wrapper = Wrapper::new().eq(primary_key, id);
+        let rows = self.restore_by_wrapper::<T>(wrapper).await?;
+        Ok(rows > 0)
+    }
+
+    async fn restore_by_wrapper<T>(&self, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        let Some(logic) = T::logic_delete() else {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "{} has no logic delete column to restore",
+                T::table_name()
+            )));
+        };
+        let (value, _) = logic.column_type_unwrap().logic_delete_value();
+        let wrapper = wrapper.only_deleted().set(logic, value);
+        self.update_by_set::<T>(wrapper).await
+    }
+
     async fn select_by_id<T, I>(&self, id: I) -> GrapefruitResult<Option<T>>
     where
         T: crate::Entity + crate::TryGetable,
@@ -178,7 +453,7 @@ impl GrapefruitRepository for Grapefruit {
             (sql, vec![id.clone().into()])
         })
         .await?;
-        let query_result = self.pool().fetch_one(&sql, params).await?;
+        let query_result = self.fetch_one(&sql, params).await?;
         let result = query_result.try_get()?;
         Ok(result)
     }
@@ -187,20 +462,104 @@ impl GrapefruitRepository for Grapefruit {
     where
         T: crate::Entity + crate::TryGetable,
     {
-        let (sql, params) =
-            build_select_sql::<T, _>(self, |index| wrapper.build(self.platform(), index + 1))
-                .await?;
-        let query_result = self.pool().fetch_all(&sql, params).await?;
+        let columns = select_columns::<T>(&wrapper);
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &columns, &wrapper);
+        let sql = wrapper.with_sql_first_last_lock(&sql, self.platform())?;
+        let query_result = self.fetch_all(&sql, params).await?;
+        let result = query_result.try_get()?;
+        Ok(result)
+    }
+
+    async fn select_one<T>(&self, wrapper: Wrapper) -> GrapefruitResult<Option<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        let columns = select_columns::<T>(&wrapper);
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &columns, &wrapper);
+        let sql = wrapper.with_sql_first_last_limit_lock(&sql, "LIMIT 1", self.platform())?;
+        let query_result = self.fetch_one(&sql, params).await?;
+        let result = query_result.try_get()?;
+        Ok(result)
+    }
+
+    async fn select_join<R>(&self, join: JoinWrapper) -> GrapefruitResult<Vec<R>>
+    where
+        R: crate::JoinRow,
+    {
+        if R::arity() != join.tables.len() {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "join of {} tables can not be read into {} values",
+                join.tables.len(),
+                R::arity()
+            )));
+        }
+        let (sql, vals) = join.build(&join.prefixed_columns(), self.platform())?;
+        let sql = join
+            .wrapper
+            .with_sql_first_last_lock(&sql, self.platform())?;
+        let query_result = self.fetch_all(&sql, vals.into()).await?;
+        let prefixes = join.tables.iter().map(|t| t.prefix()).collect::<Vec<_>>();
+        query_result
+            .results
+            .iter()
+            .map(|result| R::try_get_join(result, &prefixes))
+            .collect()
+    }
+
+    async fn select_join_as<P>(&self, join: JoinWrapper) -> GrapefruitResult<Vec<P>>
+    where
+        P: crate::TryGetable + Send,
+    {
+        let columns = if join.select.is_empty() {
+            join.prefixed_columns()
+        } else {
+            join.select.clone()
+        };
+        let (sql, vals) = join.build(&columns, self.platform())?;
+        let sql = join
+            .wrapper
+            .with_sql_first_last_lock(&sql, self.platform())?;
+        let query_result = self.fetch_all(&sql, vals.into()).await?;
         let result = query_result.try_get()?;
         Ok(result)
     }
 
+    async fn select_maps<T>(
+        &self,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<HashMap<String, Value>>>
+    where
+        T: crate::Entity,
+    {
+        let columns = select_columns::<T>(&wrapper);
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &columns, &wrapper);
+        let sql = wrapper.with_sql_first_last_lock(&sql, self.platform())?;
+        let query_result = self.fetch_all(&sql, params).await?;
+        query_result.try_get_maps()
+    }
+
+    async fn select_stream<T>(&self, wrapper: Wrapper) -> BoxStream<'static, GrapefruitResult<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        let columns = select_columns::<T>(&wrapper);
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &columns, &wrapper);
+        let sql = match wrapper.with_sql_first_last_lock(&sql, self.platform()) {
+            Ok(sql) => sql,
+            Err(e) => return stream::once(async { Err(e) }).boxed(),
+        };
+        self.fetch_stream(sql, params)
+            .map(|row| row?.try_get::<T>().map(Option::unwrap_or_default))
+            .boxed()
+    }
+
     async fn select_all<T>(&self) -> GrapefruitResult<Vec<T>>
     where
         T: crate::Entity + crate::TryGetable,
     {
-        let (sql, _) = build_select_sql::<T, _>(self, |_| ("".to_owned(), vec![])).await?;
-        let query_result = self.pool().fetch_all(&sql, crate::Params::Null).await?;
+        let (sql, params) =
+            build_select_sql::<T, _>(self, |_| ("1 = 1".to_owned(), vec![])).await?;
+        let query_result = self.fetch_all(&sql, params).await?;
         let result = query_result.try_get()?;
         Ok(result)
     }
@@ -211,7 +570,7 @@ impl GrapefruitRepository for Grapefruit {
     {
         let sql = format!("SELECT COUNT(1) FROM {} WHERE 1 = 1", T::table_name(),);
         let (sql, params) = build_logic_delete::<T>(sql, vec![], self.platform());
-        let query_result = self.pool().fetch_one(&sql, params).await?;
+        let query_result = self.fetch_one(&sql, params).await?;
         let result = query_result.try_get()?;
         Ok(result.unwrap_or(0))
     }
@@ -220,14 +579,11 @@ impl GrapefruitRepository for Grapefruit {
     where
         T: crate::Entity,
     {
-        let (build_sql, vals) = wrapper.build(self.platform(), 1);
-        let sql = format!(
-            "SELECT COUNT(1) FROM {} WHERE {}",
-            T::table_name(),
-            build_sql,
-        );
-        let (sql, params) = build_logic_delete::<T>(sql, vals, self.platform());
-        let query_result = self.pool().fetch_one(&sql, params).await?;
+        let columns = ["COUNT(1)".to_string()];
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &columns, &wrapper);
+        // a trailing clause such as FOR UPDATE does not apply to an aggregate
+        let sql = wrapper.with_sql_first(&sql);
+        let query_result = self.fetch_one(&sql, params).await?;
         let result = query_result.try_get()?;
         Ok(result.unwrap_or(0))
     }
@@ -242,28 +598,188 @@ impl GrapefruitRepository for Grapefruit {
     where
         T: crate::Entity + crate::TryGetable,
     {
-        let (build_sql, vals) = wrapper.build(self.platform(), 1);
-        let select_colums = T::select_columns();
-        let sql = format!(
-            "SELECT {} FROM {}  WHERE {}",
-            select_colums.join(","),
-            T::table_name(),
-            build_sql,
-        );
-        let (sql, params) = build_logic_delete::<T>(sql, vals, self.platform());
-        // 构建条数查询
-        let count_sql = format!("SELECT COUNT(1) FROM ( {} ) t", sql);
-        let mut page = Page::new(page, row);
-        let count_query_result = self.pool().fetch_one(&count_sql, params.clone()).await?;
-        let count_result: i64 = count_query_result.try_get()?.unwrap_or(0);
-        page.total = count_result;
-        if count_result <= 0 {
-            return Ok(page);
+        self.page_by_request::<T>(PageRequest::new(page, row), wrapper)
+            .await
+    }
+
+    async fn page_by_request<T>(
+        &self,
+        request: PageRequest,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Page<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        request.validate()?;
+        let wrapper = request.apply::<T>(wrapper)?;
+        let select_colums = select_columns::<T>(&wrapper);
+        let mut page = Page::new(request.page, request.rows);
+        page.search_count = request.search_count;
+        if request.search_count {
+            // 构建条数查询; sql_last only goes to the page query
+            let mut count_wrapper = wrapper.clone();
+            count_wrapper.expression.order_by = Segment::OrderBy(vec![]);
+            let (sql, params) = build_select_wrapper_sql::<T>(self, &select_colums, &count_wrapper);
+            let count_sql = wrapper.with_sql_first(&format!("SELECT COUNT(1) FROM ( {} ) t", sql));
+            let count_query_result = self.fetch_one(&count_sql, params).await?;
+            let count_result: i64 = count_query_result.try_get()?.unwrap_or(0);
+            page.total = count_result;
+            if count_result <= 0 {
+                return Ok(page);
+            }
         }
 
-        let query_sql = format!("{} limit {} offset {} ", sql, page.limit(), page.offset());
-        let query_result = self.pool().fetch_all(&query_sql, params).await?;
-        page.records = query_result.try_get::<T>()?;
+        // without a count, one extra row tells whether there is a next page
+        let limit = match request.search_count {
+            true => page.limit(),
+            false => page.limit() + 1,
+        };
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &select_colums, &wrapper);
+        let limit = format!("limit {} offset {}", limit, page.offset());
+        let query_sql = wrapper.with_sql_first_last_limit_lock(&sql, &limit, self.platform())?;
+        let query_result = self.fetch_all(&query_sql, params).await?;
+        let mut records = query_result.try_get::<T>()?;
+        if !request.search_count {
+            page.more = records.len() as i64 > page.limit();
+            records.truncate(page.limit() as usize);
+        }
+        page.records = records;
         Ok(page)
     }
+
+    async fn cursor_page<T>(
+        &self,
+        wrapper: Wrapper,
+        cursor: Option<Cursor>,
+        size: i64,
+        search_count: bool,
+    ) -> GrapefruitResult<CursorPage<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        if size < 1 {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "cursor page size must be at least 1, got {}",
+                size
+            )));
+        }
+        let keys = cursor_keys::<T>(&wrapper)?;
+        let total = if search_count {
+            // the order does not change the count, and an aggregate can not
+            // be ordered by a column it does not group by
+            let mut count_wrapper = wrapper.clone();
+            count_wrapper.expression.order_by = Segment::OrderBy(vec![]);
+            let columns = ["COUNT(1)".to_string()];
+            let (sql, params) = build_select_wrapper_sql::<T>(self, &columns, &count_wrapper);
+            let sql = count_wrapper.with_sql_first(&sql);
+            let count: Option<i64> = self.fetch_one(&sql, params).await?.try_get()?;
+            Some(count.unwrap_or(0))
+        } else {
+            None
+        };
+
+        let seek = seek_wrapper(&wrapper, &keys, cursor.as_ref())?;
+        let (sql, params) = build_select_wrapper_sql::<T>(self, &select_columns::<T>(&seek), &seek);
+        // one extra row tells whether there is a page beyond this one
+        let limit = format!("LIMIT {}", size + 1);
+        let sql = seek.with_sql_first_last_limit_lock(&sql, &limit, self.platform())?;
+        let records = self.fetch_all(&sql, params).await?.try_get::<T>()?;
+        Ok(CursorPage::new(
+            records,
+            &keys,
+            size as usize,
+            cursor.as_ref(),
+            total,
+        ))
+    }
+
+    async fn query_as<T>(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<Vec<T>>
+    where
+        T: crate::TryGetable + Send,
+    {
+        let (sql, vals) = build_named_sql(sql, &params, self.platform())?;
+        let query_result = self.fetch_all(&sql, vals.into()).await?;
+        let result = query_result.try_get()?;
+        Ok(result)
+    }
+
+    async fn query_maps(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<Vec<HashMap<String, Value>>> {
+        let (sql, vals) = build_named_sql(sql, &params, self.platform())?;
+        let query_result = self.fetch_all(&sql, vals.into()).await?;
+        query_result.try_get_maps()
+    }
+
+    async fn execute_raw(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<u64> {
+        let (sql, vals) = build_named_sql(sql, &params, self.platform())?;
+        let result = self.execute(&sql, vals.into()).await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn select_aggregate<T, R>(
+        &self,
+        aggregate: Aggregate,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Option<R>>
+    where
+        T: crate::Entity,
+        R: crate::ValueType + Send,
+    {
+        let column = format!("{} AS {}", aggregate.sql(), aggregate.name());
+        let (sql, params) = build_aggregate_sql::<T>(self, &[column], &wrapper);
+        let query_result = self.fetch_one(&sql, params).await?;
+        match query_result.try_get_value(0)? {
+            Some(value) => value.convert(),
+            None => Ok(None),
+        }
+    }
+
+    async fn select_grouped<T>(
+        &self,
+        aggregates: &[Aggregate],
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<(HashMap<String, Value>, HashMap<String, Value>)>>
+    where
+        T: crate::Entity,
+    {
+        let keys = wrapper
+            .expression
+            .group_by
+            .get_group_by()
+            .unwrap_or_default();
+        if keys.is_empty() {
+            return Err(GrapefruitError::ObjectValidError(
+                "select_grouped requires a group_by column".to_string(),
+            ));
+        }
+        let mut columns = keys.clone();
+        columns.extend(
+            aggregates
+                .iter()
+                .map(|aggregate| format!("{} AS {}", aggregate.sql(), aggregate.name())),
+        );
+        let (sql, params) = build_aggregate_sql::<T>(self, &columns, &wrapper);
+        let query_result = self.fetch_all(&sql, params).await?;
+        let mut groups = Vec::with_capacity(query_result.results.len());
+        for result in query_result.results.iter() {
+            let mut values = result.try_get_map()?;
+            let group = keys
+                .iter()
+                .filter_map(|key| values.remove_entry(key))
+                .collect::<HashMap<_, _>>();
+            groups.push((group, values));
+        }
+        Ok(groups)
+    }
 }
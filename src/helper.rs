@@ -1,112 +1,476 @@
-use crate::{Column, Entity, Grapefruit, GrapefruitResult, Params, Platform, Value};
+use std::collections::HashMap;
+
+use crate::{
+    Column, ColumnInfo, ColumnStrategy, Entity, Grapefruit, GrapefruitError, GrapefruitResult,
+    LogicDeleteScope, Params, Platform, Value, Wrapper,
+};
 
 pub static COMMA: &str = ",";
 
+/// Build the INSERT statements for `entities`. Rows whose column strategies
+/// leave out different columns cannot share a VALUES list, so statements are
+/// produced per distinct column shape, in order of first appearance, and each
+/// shape is split further to stay within the platform's bind parameter limit.
 #[inline]
 pub async fn build_insert_sql<T>(
     entities: &[&T],
     grapefruit: &Grapefruit,
-) -> GrapefruitResult<(String, Params)>
+) -> GrapefruitResult<Vec<(String, Params)>>
+where
+    T: Entity,
+{
+    let mut shapes: Vec<(Vec<String>, Vec<Vec<Value>>)> = Vec::new();
+    for entity in entities.iter() {
+        let (columns, row) = build_insert_row(*entity, grapefruit, false).await?;
+        match shapes.iter_mut().find(|(shape, _)| *shape == columns) {
+            Some((_, rows)) => rows.push(row),
+            None => shapes.push((columns, vec![row])),
+        }
+    }
+
+    let platform = grapefruit.platform();
+    let mut statements = Vec::with_capacity(shapes.len());
+    for (columns, rows) in shapes {
+        for rows in chunk_rows(rows, columns.len(), 0, platform) {
+            let (sql, values) = build_values_sql::<T>(&columns, rows, platform);
+            statements.push((sql, values.into()));
+        }
+    }
+    Ok(statements)
+}
+
+/// Split `rows` so that each statement stays within the platform's bind
+/// parameter limit, keeping `reserved` parameters free for other clauses
+fn chunk_rows(
+    rows: Vec<Vec<Value>>,
+    columns: usize,
+    reserved: usize,
+    platform: &Platform,
+) -> Vec<Vec<Vec<Value>>> {
+    let available = platform.max_bind_params().saturating_sub(reserved);
+    let size = (available / columns.max(1)).max(1);
+    let mut chunks = Vec::with_capacity(rows.len() / size + 1);
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        chunks.push(rows.by_ref().take(size).collect());
+    }
+    chunks
+}
+
+/// Build the INSERT of a single `entity`, along with the primary key value
+/// it writes. The key is `None` when the database assigns it.
+#[inline]
+pub async fn build_insert_one_sql<T>(
+    entity: &T,
+    grapefruit: &Grapefruit,
+) -> GrapefruitResult<(String, Params, Option<Value>)>
+where
+    T: Entity,
+{
+    let (columns, row) = build_insert_row(entity, grapefruit, false).await?;
+    let id = T::primary_key()
+        .and_then(|primary_key| columns.iter().position(|c| *c == primary_key.name))
+        .map(|index| row[index].clone());
+    let (sql, values) = build_values_sql::<T>(&columns, vec![row], grapefruit.platform());
+    Ok((sql, values.into(), id))
+}
+
+/// Columns and values written for one entity. With `keep_id` a primary key
+/// already set on the entity is written as is instead of being generated.
+async fn build_insert_row<T>(
+    entity: &T,
+    grapefruit: &Grapefruit,
+    keep_id: bool,
+) -> GrapefruitResult<(Vec<String>, Vec<Value>)>
 where
     T: Entity,
 {
     let column_map = T::columns();
-    let insert_columns = T::insert_columns();
-    let mut values = Vec::new();
-    let mut marks_str = Vec::new();
-    let mut i = 1;
-    for entity in entities.into_iter() {
-        let data = entity.to_value();
-        let mut marks = Vec::new();
-        for column in insert_columns.iter() {
-            let column_info = column_map.get(column).unwrap();
-            let value = data
-                .get(column)
-                .expect(format!("{} column value not found.", column.as_str()).as_str());
-            if column_info.is_table_id() {
-                match column_info.id_type() {
-                    crate::IdType::Auto => {}
-                    crate::IdType::Generator => {
-                        let id = grapefruit.generator_id().await;
-                        values.push(id);
-                    }
-                    crate::IdType::Input => {
-                        values.push(value.clone());
-                    }
-                }
-            } else {
-                match column_info.fill {
-                    crate::Fill::Insert | crate::Fill::InsertAndUpdate => {
-                        let v = &grapefruit.get_insert_fill(&column)?;
-                        values.push(v.clone());
-                    }
-                    _ => {
-                        values.push(value.clone());
-                    }
-                }
+    let mut insert_columns = T::insert_columns();
+    if let Some(primary_key) = T::primary_key() {
+        if keep_id && !insert_columns.contains(&primary_key.name) {
+            insert_columns.insert(0, primary_key.name);
+        }
+    }
+    let data = entity.to_value();
+    let mut columns = Vec::with_capacity(insert_columns.len());
+    let mut row = Vec::with_capacity(insert_columns.len());
+    for column in insert_columns.iter() {
+        let column_info = column_map.get(column).unwrap();
+        let value = data
+            .get(column)
+            .expect(format!("{} column value not found.", column.as_str()).as_str());
+        let Some(value) = insert_value(column_info, value, grapefruit, keep_id).await? else {
+            continue;
+        };
+        if !column_info.insert_strateg.accepts(&value) {
+            continue;
+        }
+        columns.push(column.clone());
+        row.push(value);
+    }
+    Ok((columns, row))
+}
+
+/// Value written for a column on insert: a generated id or insert fill takes
+/// the place of the entity's own value. `None` when the database assigns it.
+pub(crate) async fn insert_value(
+    column_info: &ColumnInfo,
+    value: &Value,
+    grapefruit: &Grapefruit,
+    keep_id: bool,
+) -> GrapefruitResult<Option<Value>> {
+    let value = if column_info.is_table_id() {
+        match column_info.id_type() {
+            _ if keep_id && !value.is_none() => value.clone(),
+            crate::IdType::Auto => return Ok(None),
+            crate::IdType::Generator => grapefruit.generator_id().await,
+            crate::IdType::Input => value.clone(),
+        }
+    } else {
+        match column_info.fill {
+            crate::Fill::Insert | crate::Fill::InsertAndUpdate => {
+                grapefruit.get_insert_fill(&column_info.name)?
             }
-            marks.push(grapefruit.platform().mark(i));
-            i += 1;
+            _ => value.clone(),
+        }
+    };
+    Ok(Some(value))
+}
+
+/// `INSERT INTO ... VALUES` for rows sharing the same `columns`
+fn build_values_sql<T>(
+    columns: &[String],
+    rows: Vec<Vec<Value>>,
+    platform: &Platform,
+) -> (String, Vec<Value>)
+where
+    T: Entity,
+{
+    let mut values = Vec::with_capacity(columns.len() * rows.len());
+    let mut marks_str = Vec::with_capacity(rows.len());
+    for row in rows {
+        let mut marks = Vec::with_capacity(row.len());
+        for value in row {
+            values.push(value);
+            marks.push(platform.mark(values.len()));
         }
         marks_str.push(format!("({})", marks.join(",")));
     }
-
     let sql = format!(
         "INSERT INTO {} ({}) VALUES {} ",
         T::table_name(),
-        insert_columns.join(","),
+        columns.join(","),
         marks_str.join(",")
     );
-    Ok((sql, values.into()))
+    (sql, values)
 }
 
+/// Build the upsert statements for `entities`, resolving conflicts on
+/// `conflict` (the primary key when empty). Rows are grouped by column shape
+/// like `build_insert_sql`, except that rows carrying a version get their own
+/// statement, whose update branch only applies while the stored version still
+/// matches. The returned flag marks those guarded statements.
 #[inline]
-pub async fn build_update_sql<T, F>(
+pub async fn build_upsert_sql<T>(
+    entities: &[&T],
+    grapefruit: &Grapefruit,
+    conflict: &[&str],
+) -> GrapefruitResult<Vec<(String, Params, bool)>>
+where
+    T: Entity,
+{
+    let conflict = match conflict.is_empty() {
+        true => vec![T::primary_key().alias()?],
+        false => conflict.iter().map(|c| c.to_string()).collect(),
+    };
+    let mut shapes: Vec<(Vec<String>, Vec<Vec<Value>>)> = Vec::new();
+    let mut guarded = Vec::new();
+    for entity in entities.iter() {
+        let (columns, row) = build_insert_row(*entity, grapefruit, true).await?;
+        match version_value::<T>(&entity.to_value()) {
+            // MySQL reports a skipped update like an insert, so a stale
+            // version would pass unnoticed
+            Some(_) if !grapefruit.platform().upsert_where() => {
+                return Err(GrapefruitError::PlatformError(format!(
+                    "upsert of a versioned {} is not supported on {:?}",
+                    T::table_name(),
+                    grapefruit.platform()
+                )));
+            }
+            Some(version) => guarded.push((columns, row, version)),
+            None => match shapes.iter_mut().find(|(shape, _)| *shape == columns) {
+                Some((_, rows)) => rows.push(row),
+                None => shapes.push((columns, vec![row])),
+            },
+        }
+    }
+
+    // the update branch binds at most a fill value per column and the version
+    let reserved = T::update_columns().len() + 1;
+    let mut statements = Vec::with_capacity(shapes.len() + guarded.len());
+    for (columns, rows) in shapes {
+        for rows in chunk_rows(rows, columns.len(), reserved, grapefruit.platform()) {
+            let (sql, values) =
+                build_upsert_statement::<T>(&columns, rows, &conflict, None, grapefruit)?;
+            statements.push((sql, values.into(), false));
+        }
+    }
+    for (columns, row, version) in guarded {
+        let (sql, values) =
+            build_upsert_statement::<T>(&columns, vec![row], &conflict, Some(version), grapefruit)?;
+        statements.push((sql, values.into(), true));
+    }
+    Ok(statements)
+}
+
+fn build_upsert_statement<T>(
+    columns: &[String],
+    rows: Vec<Vec<Value>>,
+    conflict: &[String],
+    version: Option<(ColumnInfo, Value)>,
+    grapefruit: &Grapefruit,
+) -> GrapefruitResult<(String, Vec<Value>)>
+where
+    T: Entity,
+{
+    let platform = grapefruit.platform();
+    let table_name = T::table_name();
+    let column_map = T::columns();
+    let (sql, mut values) = build_values_sql::<T>(columns, rows, platform);
+
+    // Each assignment takes either an expression or a bound value
+    let mut assignments: Vec<(String, Result<String, Value>)> = Vec::new();
+    for column in T::update_columns().iter() {
+        let column_info = column_map.get(column).unwrap();
+        if column_info.is_table_id()
+            || conflict.contains(column)
+            || conflict.contains(&column_info.alias)
+        {
+            continue;
+        }
+        if column_info.is_version() {
+            // a row without a version still bumps the stored one, so that
+            // versioned writers notice the change; a versioned row sets it below
+            if version.is_none() {
+                let stored = format!("{}.{}", table_name, platform.symbol(&column_info.alias));
+                let increment = column_info.version_increment(&stored)?;
+                assignments.push((column_info.alias.clone(), Ok(increment)));
+            }
+            continue;
+        }
+        match column_info.fill {
+            crate::Fill::Update | crate::Fill::InsertAndUpdate => {
+                let value = grapefruit.get_update_fill(column)?;
+                assignments.push((column.clone(), Err(value)));
+            }
+            _ if !columns.contains(column) => {}
+            _ => {
+                let excluded = platform.excluded(column);
+                let expr = match column_info.update_strateg {
+                    ColumnStrategy::NotNull => {
+                        format!("COALESCE({}, {}.{})", excluded, table_name, column)
+                    }
+                    _ => excluded,
+                };
+                assignments.push((column.clone(), Ok(expr)));
+            }
+        }
+    }
+    if let Some((column_info, value)) = &version {
+        assignments.push((column_info.alias.clone(), Err(value.next_version()?)));
+    }
+
+    if assignments.is_empty() {
+        let sql = format!("{} {}", sql, platform.on_conflict_do_nothing(conflict));
+        return Ok((sql, values));
+    }
+
+    let mut sets = Vec::with_capacity(assignments.len());
+    for (column, assignment) in assignments {
+        let expr = assignment.unwrap_or_else(|value| {
+            values.push(value);
+            platform.mark(values.len())
+        });
+        sets.push(format!("{} = {}", platform.symbol(&column), expr));
+    }
+    let mut sql = format!(
+        "{} {} {}",
+        sql,
+        platform.on_conflict(conflict),
+        sets.join(",")
+    );
+    if let Some((column_info, value)) = version {
+        values.push(value);
+        sql = format!(
+            "{} WHERE {}.{} = {}",
+            sql,
+            table_name,
+            platform.symbol(&column_info.alias),
+            platform.mark(values.len())
+        );
+    }
+    Ok((sql, values))
+}
+
+/// Build the UPDATE of the rows matching `wrapper` from `entity`. A version
+/// on the entity is bumped and its current value added to the conditions.
+#[inline]
+pub async fn build_update_sql<T>(
     entity: &T,
     grapefruit: &Grapefruit,
-    f: F,
+    wrapper: &Wrapper,
 ) -> GrapefruitResult<(String, Params)>
 where
     T: Entity,
-    F: Fn(usize) -> (String, Vec<Value>),
 {
     let column_map = T::columns();
     let data = entity.to_value();
     let update_columns = T::update_columns();
+    let version = version_value::<T>(&data);
     let mut values = Vec::with_capacity(update_columns.len() + 1);
     let mut columns = Vec::with_capacity(update_columns.len());
-    for (index, column) in update_columns.iter().enumerate() {
-        let value = data.get(column).unwrap();
+    for column in update_columns.iter() {
         let column_info = column_map.get(column).unwrap();
-        match column_info.fill {
-            crate::Fill::Insert | crate::Fill::InsertAndUpdate => {
-                let v = &grapefruit.get_insert_fill(&column)?;
-                values.push(v.clone());
-            }
-            _ => {
-                values.push(value.clone());
+        // bumped below when the entity carries a version, and otherwise
+        // left as stored so that later updates stay locked
+        if column_info.is_version() {
+            continue;
+        }
+        let value = data.get(column).unwrap();
+        let value = match column_info.fill {
+            crate::Fill::Update | crate::Fill::InsertAndUpdate => {
+                grapefruit.get_update_fill(column)?
             }
+            _ => value.clone(),
+        };
+        if !column_info.update_strateg.accepts(&value) {
+            continue;
         }
+        values.push(value);
         columns.push(format!(
             "{} = {}",
             grapefruit.platform().symbol(column),
-            grapefruit.platform().mark(index + 1)
+            grapefruit.platform().mark(values.len())
+        ));
+    }
+    if let Some((column_info, value)) = &version {
+        values.push(value.next_version()?);
+        columns.push(format!(
+            "{} = {}",
+            grapefruit.platform().symbol(&column_info.alias),
+            grapefruit.platform().mark(values.len())
+        ));
+    }
+
+    if columns.is_empty() {
+        return Err(GrapefruitError::EmptyEntity);
+    }
+
+    let platform = grapefruit.platform();
+    let guard = version.map(|(column_info, value)| (platform.symbol(&column_info.alias), value));
+    let (build_sql, vals) =
+        build_guarded_wrapper_sql::<T>(wrapper, platform, values.len() + 1, guard);
+    values.extend(vals);
+    let sql = format!(
+        "UPDATE {} SET {} WHERE {} ",
+        T::table_name(),
+        columns.join(","),
+        build_sql,
+    );
+    Ok((sql, values.into()))
+}
+
+/// Build an UPDATE from the SET clauses of `wrapper`. Update fill and the
+/// version bump apply to columns the wrapper does not set itself; the bump
+/// is not guarded, so add a condition on the version to lock optimistically.
+#[inline]
+pub async fn build_update_set_sql<T>(
+    grapefruit: &Grapefruit,
+    wrapper: &Wrapper,
+) -> GrapefruitResult<(String, Params)>
+where
+    T: Entity,
+{
+    if let Some(error) = &wrapper.error {
+        return Err(GrapefruitError::ObjectValidError(error.clone()));
+    }
+    if wrapper.column_set.is_empty() && wrapper.null_set.is_empty() && wrapper.sql_set.is_empty() {
+        return Err(GrapefruitError::ObjectValidError(
+            "update_by_set requires at least one set clause".to_string(),
+        ));
+    }
+    let platform = grapefruit.platform();
+    let column_map = T::columns();
+    let mut values = Vec::with_capacity(wrapper.column_set.len());
+    let mut columns = Vec::with_capacity(wrapper.column_set.len() + wrapper.sql_set.len());
+    let mut set_columns = Vec::with_capacity(wrapper.column_set.len());
+    for (column, value) in wrapper.column_set.iter() {
+        values.push(value.clone());
+        columns.push(format!(
+            "{} = {}",
+            platform.symbol(column),
+            platform.mark(values.len())
         ));
+        set_columns.push(column.clone());
+    }
+    for column in wrapper.null_set.iter() {
+        columns.push(format!("{} = NULL", platform.symbol(column)));
+        set_columns.push(column.clone());
+    }
+    columns.extend(wrapper.sql_set.iter().cloned());
+
+    for column in T::update_columns().iter() {
+        let column_info = column_map.get(column).unwrap();
+        if set_columns.contains(&column_info.alias) {
+            continue;
+        }
+        if column_info.is_version() {
+            let column = platform.symbol(&column_info.alias);
+            let increment = column_info.version_increment(&column)?;
+            columns.push(format!("{} = {}", column, increment));
+        } else if matches!(
+            column_info.fill,
+            crate::Fill::Update | crate::Fill::InsertAndUpdate
+        ) {
+            values.push(grapefruit.get_update_fill(column)?);
+            columns.push(format!(
+                "{} = {}",
+                platform.symbol(&column_info.alias),
+                platform.mark(values.len())
+            ));
+        }
     }
 
-    let (build_sql, vals) = f(columns.len());
-    values.extend_from_slice(&vals);
+    // the logic delete condition is bracketed with the wrapper's, so an
+    // `or` in the wrapper can not reach the rows outside its scope
+    let (build_sql, vals) = build_wrapper_sql::<T>(wrapper, platform, values.len() + 1);
+    values.extend(vals);
     let sql = format!(
         "UPDATE {} SET {} WHERE {} ",
         T::table_name(),
         columns.join(","),
         build_sql,
     );
-    println!("{:?}", sql);
-    println!("{:?}", values);
-    Ok(build_logic_delete::<T>(sql, values, grapefruit.platform()))
+    Ok((sql, values.into()))
 }
 
+/// Version column and its current value, when the entity takes part in
+/// optimistic locking. A `None` version disables the lock.
+pub fn version_value<T>(data: &HashMap<String, Value>) -> Option<(ColumnInfo, Value)>
+where
+    T: Entity,
+{
+    let column_info = T::version()?;
+    let value = data.get(&column_info.name)?;
+    if value.is_none() {
+        return None;
+    }
+    Some((column_info, value.clone()))
+}
+
+/// Build the DELETE, or the logic delete UPDATE, of the rows picked by the
+/// primary key condition of `f`
 #[inline]
 pub async fn build_delete_sql<T, F>(
     grapefruit: &Grapefruit,
@@ -116,9 +480,31 @@ where
     T: Entity,
     F: Fn(usize) -> (String, Vec<Value>),
 {
-    let logic_delete = T::logic_delete();
+    let (sql, vals) = delete_statement::<T, F>(grapefruit, f);
+    Ok(build_logic_delete::<T>(sql, vals, grapefruit.platform()))
+}
 
-    let (sql, vals) = match logic_delete {
+/// Like `build_delete_sql` for the rows matching `wrapper`
+#[inline]
+pub async fn build_delete_wrapper_sql<T>(
+    grapefruit: &Grapefruit,
+    wrapper: &Wrapper,
+) -> GrapefruitResult<(String, Params)>
+where
+    T: Entity,
+{
+    let (sql, vals) = delete_statement::<T, _>(grapefruit, |index| {
+        build_wrapper_sql::<T>(wrapper, grapefruit.platform(), index + 1)
+    });
+    Ok((sql, vals.into()))
+}
+
+fn delete_statement<T, F>(grapefruit: &Grapefruit, f: F) -> (String, Vec<Value>)
+where
+    T: Entity,
+    F: Fn(usize) -> (String, Vec<Value>),
+{
+    match T::logic_delete() {
         Some(v) => {
             let (build_sql, mut vals) = f(1);
             let (_, value) = v.column_type_unwrap().logic_delete_value();
@@ -137,9 +523,7 @@ where
             let sql = format!("DELETE FROM {} WHERE {}", T::table_name(), build_sql,);
             (sql, vals)
         }
-    };
-
-    Ok(build_logic_delete::<T>(sql, vals, grapefruit.platform()))
+    }
 }
 
 #[inline]
@@ -147,15 +531,28 @@ pub async fn build_select_sql<T, F>(
     grapefruit: &Grapefruit,
     f: F,
 ) -> GrapefruitResult<(String, Params)>
+where
+    T: Entity,
+    F: Fn(usize) -> (String, Vec<Value>),
+{
+    build_select_columns_sql::<T, F>(grapefruit, &T::select_columns(), f).await
+}
+
+/// Like `build_select_sql`, querying `columns` instead of every selectable one
+#[inline]
+pub async fn build_select_columns_sql<T, F>(
+    grapefruit: &Grapefruit,
+    columns: &[String],
+    f: F,
+) -> GrapefruitResult<(String, Params)>
 where
     T: Entity,
     F: Fn(usize) -> (String, Vec<Value>),
 {
     let (build_sql, vals) = f(0);
-    let select_colums = T::select_columns();
     let sql = format!(
         "SELECT {} FROM {}  WHERE {}",
-        select_colums.join(","),
+        columns.join(","),
         T::table_name(),
         build_sql,
     );
@@ -163,6 +560,165 @@ where
     Ok(build_logic_delete::<T>(sql, vals, grapefruit.platform()))
 }
 
+/// Columns queried for `wrapper`: its `select` list when set, otherwise the
+/// entity's selectable columns minus the ones it excludes
+pub fn select_columns<T>(wrapper: &Wrapper) -> Vec<String>
+where
+    T: Entity,
+{
+    if let Some(sql_select) = &wrapper.sql_select {
+        return vec![sql_select.clone()];
+    }
+    let column_map = T::columns();
+    T::select_columns()
+        .into_iter()
+        .filter(|column| {
+            let alias = column_map.get(column).map(|c| &c.alias);
+            !wrapper.sql_exclude.contains(column)
+                && !alias.is_some_and(|alias| wrapper.sql_exclude.contains(alias))
+        })
+        .collect()
+}
+
+/// Rewrite the `:name` placeholders of `sql` into `platform` marks, binding
+/// `params` in order and expanding arrays into one mark per element.
+/// Quoted text and `::` casts are left alone.
+pub fn build_named_sql(
+    sql: &str,
+    params: &HashMap<String, Value>,
+    platform: &Platform,
+) -> GrapefruitResult<(String, Vec<Value>)> {
+    let mut build_sql = String::with_capacity(sql.len());
+    let mut values = Vec::new();
+    let mut quote = None;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            build_sql.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => {
+                quote = Some(c);
+                build_sql.push(c);
+            }
+            ':' if chars.peek() == Some(&':') => {
+                chars.next();
+                build_sql.push_str("::");
+            }
+            ':' if chars.peek().is_some_and(|n| n.is_alphabetic() || *n == '_') => {
+                let mut name = String::new();
+                while let Some(n) = chars.next_if(|n| n.is_alphanumeric() || *n == '_') {
+                    name.push(n);
+                }
+                let Some(val) = params.get(&name) else {
+                    return Err(GrapefruitError::NoSuchValueError(name));
+                };
+                let vals = match val {
+                    Value::Array(_, _) => val.get_array(),
+                    _ => vec![val.clone()],
+                };
+                for (i, val) in vals.into_iter().enumerate() {
+                    if i != 0 {
+                        build_sql.push_str(", ");
+                    }
+                    values.push(val);
+                    build_sql.push_str(&platform.mark(values.len()));
+                }
+            }
+            _ => build_sql.push(c),
+        }
+    }
+    Ok((build_sql, values))
+}
+
+/// WHERE clause of `wrapper` with the logic delete condition of `T`, placed
+/// ahead of its GROUP BY and ORDER BY and apart from its `or` conditions
+pub fn build_wrapper_sql<T>(
+    wrapper: &Wrapper,
+    platform: &Platform,
+    index: usize,
+) -> (String, Vec<Value>)
+where
+    T: Entity,
+{
+    build_guarded_wrapper_sql::<T>(wrapper, platform, index, None)
+}
+
+/// Like `build_wrapper_sql`, with the `column = value` of `guard` added to
+/// the logic delete condition
+fn build_guarded_wrapper_sql<T>(
+    wrapper: &Wrapper,
+    platform: &Platform,
+    index: usize,
+    guard: Option<(String, Value)>,
+) -> (String, Vec<Value>)
+where
+    T: Entity,
+{
+    let conditions = logic_delete_filter::<T>(&wrapper.logic_delete)
+        .into_iter()
+        .chain(guard)
+        .collect::<Vec<_>>();
+    if conditions.is_empty() {
+        return wrapper.build(platform, index);
+    }
+    wrapper.and_all(conditions).build(platform, index)
+}
+
+/// Logic delete column and the value `scope` keeps, `None` when it keeps
+/// every row or the entity has no logic delete column
+pub fn logic_delete_filter<T>(scope: &LogicDeleteScope) -> Option<(String, Value)>
+where
+    T: Entity,
+{
+    let logic = T::logic_delete()?;
+    let (value, deleted) = logic.column_type_unwrap().logic_delete_value();
+    match scope {
+        LogicDeleteScope::NotDeleted => Some((logic.alias, value)),
+        LogicDeleteScope::Deleted => Some((logic.alias, deleted)),
+        LogicDeleteScope::All => None,
+    }
+}
+
+/// SELECT of `columns`, usually aggregates, over the rows matching `wrapper`
+pub fn build_aggregate_sql<T>(
+    grapefruit: &Grapefruit,
+    columns: &[String],
+    wrapper: &Wrapper,
+) -> (String, Params)
+where
+    T: Entity,
+{
+    let (sql, params) = build_select_wrapper_sql::<T>(grapefruit, columns, wrapper);
+    (wrapper.with_sql_first_last(&sql), params)
+}
+
+/// SELECT of `columns` over the rows matching `wrapper`, leaving out its
+/// `sql_first` and `sql_last` for the caller to place
+pub fn build_select_wrapper_sql<T>(
+    grapefruit: &Grapefruit,
+    columns: &[String],
+    wrapper: &Wrapper,
+) -> (String, Params)
+where
+    T: Entity,
+{
+    let (build_sql, vals) = build_wrapper_sql::<T>(wrapper, grapefruit.platform(), 1);
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {}",
+        columns.join(","),
+        T::table_name(),
+        build_sql,
+    );
+    (sql, vals.into())
+}
+
+/// Append the condition keeping the rows not deleted to `sql`, whose WHERE
+/// clause must be a plain condition, such as one on the primary key
 #[inline]
 pub fn build_logic_delete<T>(
     sql: String,
@@ -172,15 +728,9 @@ pub fn build_logic_delete<T>(
 where
     T: Entity,
 {
-    match T::logic_delete() {
-        Some(logic) => {
-            let (value, _) = logic.column_type_unwrap().logic_delete_value();
-            let sql = format!(
-                "{} AND {} = {}",
-                sql,
-                logic.alias_unwrap(),
-                platform.mark(vals.len() + 1)
-            );
+    match logic_delete_filter::<T>(&LogicDeleteScope::NotDeleted) {
+        Some((column, value)) => {
+            let sql = format!("{} AND {} = {}", sql, column, platform.mark(vals.len() + 1));
             vals.push(value);
             (sql, vals.into())
         }
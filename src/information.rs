@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use sqlx::Row;
 
-use crate::{GrapefruitError, GrapefruitResult, QueryResult, Value};
+use crate::{ArrayType, GrapefruitError, GrapefruitResult, QueryResult, Value};
 
 pub trait PrimaryKey: Send + Sync + Clone + 'static + Into<Value> {
     type Key
@@ -55,6 +55,17 @@ pub trait Entity: 'static + Send + Sync {
 pub trait Column {
     fn column_info(&self) -> Option<ColumnInfo>;
 
+    /// Table the column belongs to, used to qualify it in joins
+    fn table_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Alias of the joined table the column is read from, `None` for the
+    /// first table of its entity
+    fn join_alias(&self) -> Option<String> {
+        None
+    }
+
     fn alias(&self) -> GrapefruitResult<String> {
         let alias_op = self.column_info().map(|v| v.alias.to_string());
         let Some(alias) = alias_op else {
@@ -76,6 +87,12 @@ pub trait Column {
 
 pub trait TryGetable: Sized + Default {
     fn try_get(result: &QueryResult) -> GrapefruitResult<Option<Self>>;
+
+    /// Read from the columns named `{prefix}{column}`; `None` when all of
+    /// them are missing or `NULL`, as for the outer side of a join
+    fn try_get_prefixed(result: &QueryResult, _prefix: &str) -> GrapefruitResult<Option<Self>> {
+        Self::try_get(result)
+    }
 }
 
 macro_rules! impl_try_get {
@@ -128,7 +145,11 @@ pub struct ColumnInfo {
     pub column_type: ColumnType,
     pub fill: Fill,
     pub is_logic_delete: bool,
-    pub version: bool,
+    /// whether the field is an `Option` that may be stored as NULL, which
+    /// the primary key never is
+    pub nullable: bool,
+    /// value type of a version column, `None` for the other columns
+    pub version: Option<ArrayType>,
 }
 
 impl ColumnInfo {
@@ -141,7 +162,34 @@ impl ColumnInfo {
     }
 
     pub fn is_version(&self) -> bool {
-        self.version
+        self.version.is_some()
+    }
+
+    /// SQL expression giving the next value of this version column, whose
+    /// name is `column` as written in the statement
+    pub fn version_increment(&self, column: &str) -> GrapefruitResult<String> {
+        match &self.version {
+            Some(
+                ArrayType::Tinyint
+                | ArrayType::Smallint
+                | ArrayType::Int
+                | ArrayType::Bigint
+                | ArrayType::TinyUnsigned
+                | ArrayType::SmallUnsigned
+                | ArrayType::Unsigned
+                | ArrayType::BigUnsigned,
+            ) => Ok(format!("{} + 1", column)),
+            Some(
+                ArrayType::ChronoDateTime
+                | ArrayType::ChronoDateTimeUtc
+                | ArrayType::ChronoDateTimeLocal
+                | ArrayType::ChronoDateTimeWithTimeZone,
+            ) => Ok("CURRENT_TIMESTAMP".to_string()),
+            _ => Err(GrapefruitError::ObjectValidError(format!(
+                "`{}` can not be used as version",
+                self.alias
+            ))),
+        }
     }
 
     pub fn is_table_id(&self) -> bool {
@@ -151,7 +199,6 @@ impl ColumnInfo {
     pub fn id_type(&self) -> IdType {
         self.column_type.get_id_type()
     }
-
 }
 
 impl Column for ColumnInfo {
@@ -200,6 +247,15 @@ impl ColumnStrategy {
             _ => ColumnStrategy::Default,
         }
     }
+
+    /// Whether a column holding `value` is written under this strategy
+    pub fn accepts(&self, value: &Value) -> bool {
+        match self {
+            ColumnStrategy::Default => true,
+            ColumnStrategy::NotNull => !value.is_none(),
+            ColumnStrategy::Never => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -0,0 +1,553 @@
+use crate::{
+    Column, ColumnInfo, Entity, GrapefruitError, GrapefruitResult, NotU8, Platform, QueryResult,
+    Segment, SegmentType, TryGetable, Value, ValueType, Wrapper,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JoinType {
+    Inner,
+    Left,
+    Right,
+}
+
+impl JoinType {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            JoinType::Inner => "INNER JOIN",
+            JoinType::Left => "LEFT JOIN",
+            JoinType::Right => "RIGHT JOIN",
+        }
+    }
+}
+
+/// A table taking part in a join
+#[derive(Clone, Debug, PartialEq)]
+pub struct JoinTable {
+    pub table_name: String,
+    pub alias: String,
+    /// join type and `ON` columns, `None` for the `FROM` table
+    pub join: Option<(JoinType, String, String)>,
+    /// selectable columns
+    pub columns: Vec<String>,
+    pub logic_delete: Option<(String, Value)>,
+}
+
+impl JoinTable {
+    fn new<T>(alias: &str, join: Option<(JoinType, String, String)>) -> Self
+    where
+        T: Entity,
+    {
+        let column_map = T::columns();
+        let columns = T::select_columns()
+            .iter()
+            .filter_map(|column| column_map.get(column).map(|c| c.alias.clone()))
+            .collect();
+        let logic_delete = T::logic_delete().map(|logic| {
+            let (value, _) = logic.column_type.logic_delete_value();
+            (logic.alias, value)
+        });
+        Self {
+            table_name: T::table_name(),
+            alias: alias.to_string(),
+            join,
+            columns,
+            logic_delete,
+        }
+    }
+
+    /// Prefix of the result columns read for this table
+    pub fn prefix(&self) -> String {
+        format!("{}_", self.alias)
+    }
+
+    fn qualify(&self, column: &str) -> String {
+        format!("{}.{}", self.alias, column)
+    }
+}
+
+/// `column` of the joined table aliased `alias`, for an entity that takes
+/// part in the join more than once
+#[derive(Debug, Clone)]
+pub struct AliasColumn<C> {
+    alias: String,
+    column: C,
+}
+
+impl<C> AliasColumn<C>
+where
+    C: Column,
+{
+    pub fn new(alias: &str, column: C) -> Self {
+        Self {
+            alias: alias.to_string(),
+            column,
+        }
+    }
+}
+
+impl<C> Column for AliasColumn<C>
+where
+    C: Column,
+{
+    fn column_info(&self) -> Option<ColumnInfo> {
+        self.column.column_info()
+    }
+
+    fn table_name(&self) -> Option<String> {
+        self.column.table_name()
+    }
+
+    fn join_alias(&self) -> Option<String> {
+        Some(self.alias.clone())
+    }
+}
+
+/// Query over several entities joined together.
+///
+/// Tables are aliased `t0`, `t1`, ... in the order they are added unless an
+/// alias is given. Conditions take any joined entity's `*Def` column and are
+/// qualified with the alias of the first table of that entity, or with the
+/// one of an `AliasColumn`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinWrapper {
+    pub tables: Vec<JoinTable>,
+    /// projection read by `select_join_as`
+    pub select: Vec<String>,
+    pub wrapper: Wrapper,
+    /// first column that could not be qualified, returned by `build`
+    pub error: Option<String>,
+}
+
+impl JoinWrapper {
+    pub fn from<T>() -> Self
+    where
+        T: Entity,
+    {
+        Self::from_as::<T>("t0")
+    }
+
+    pub fn from_as<T>(alias: &str) -> Self
+    where
+        T: Entity,
+    {
+        Self {
+            tables: vec![JoinTable::new::<T>(alias, None)],
+            select: Vec::new(),
+            wrapper: Wrapper::new(),
+            error: None,
+        }
+    }
+
+    pub fn inner_join<T>(self, column: impl Column, join_column: impl Column) -> Self
+    where
+        T: Entity,
+    {
+        let alias = self.next_alias();
+        self.join_as::<T>(JoinType::Inner, &alias, column, join_column)
+    }
+
+    pub fn left_join<T>(self, column: impl Column, join_column: impl Column) -> Self
+    where
+        T: Entity,
+    {
+        let alias = self.next_alias();
+        self.join_as::<T>(JoinType::Left, &alias, column, join_column)
+    }
+
+    pub fn right_join<T>(self, column: impl Column, join_column: impl Column) -> Self
+    where
+        T: Entity,
+    {
+        let alias = self.next_alias();
+        self.join_as::<T>(JoinType::Right, &alias, column, join_column)
+    }
+
+    /// Join `T` as `alias` on `column = join_column`. `column` belongs to a
+    /// table already in the join and `join_column` to `T`, so an entity can
+    /// be joined to itself; give `column` as an `AliasColumn` to join on
+    /// another table than the first of its entity.
+    pub fn join_as<T>(
+        mut self,
+        join_type: JoinType,
+        alias: &str,
+        column: impl Column,
+        join_column: impl Column,
+    ) -> Self
+    where
+        T: Entity,
+    {
+        let column = self.try_qualify(column);
+        let join_column = match join_column.column_info() {
+            Some(info) => Some(format!("{}.{}", alias, info.alias)),
+            None => {
+                self.fail("join column not set".to_string());
+                None
+            }
+        };
+        let join = column
+            .zip(join_column)
+            .map(|(column, join_column)| (join_type, column.alias, join_column));
+        self.tables.push(JoinTable::new::<T>(alias, join));
+        self
+    }
+
+    fn next_alias(&self) -> String {
+        format!("t{}", self.tables.len())
+    }
+
+    /// `column` qualified with the alias of its table
+    pub fn qualify<C>(&self, column: C) -> GrapefruitResult<ColumnInfo>
+    where
+        C: Column,
+    {
+        self.find_column(column)
+            .map_err(GrapefruitError::ObjectValidError)
+    }
+
+    fn find_column<C>(&self, column: C) -> Result<ColumnInfo, String>
+    where
+        C: Column,
+    {
+        let Some(mut info) = column.column_info() else {
+            return Err("join column not set".to_string());
+        };
+        let table_name = column.table_name().unwrap_or_default();
+        let alias = column.join_alias();
+        let table = self
+            .tables
+            .iter()
+            .find(|t| t.table_name == table_name && alias.as_ref().is_none_or(|a| *a == t.alias));
+        let Some(table) = table else {
+            return Err(match alias {
+                Some(alias) => format!("table `{} {}` is not part of the join", table_name, alias),
+                None => format!("table `{}` is not part of the join", table_name),
+            });
+        };
+        info.alias = table.qualify(&info.alias);
+        Ok(info)
+    }
+
+    /// Like `qualify`, keeping the first error for `build`
+    fn try_qualify<C>(&mut self, column: C) -> Option<ColumnInfo>
+    where
+        C: Column,
+    {
+        match self.find_column(column) {
+            Ok(info) => Some(info),
+            Err(error) => {
+                self.fail(error);
+                None
+            }
+        }
+    }
+
+    fn fail(&mut self, error: String) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    /// Add `column` to the projection read by `select_join_as`
+    pub fn select<C>(mut self, column: C) -> Self
+    where
+        C: Column,
+    {
+        if let Some(column) = self.try_qualify(column) {
+            self.select.push(column.alias);
+        }
+        self
+    }
+
+    /// Add `column` to the projection under the name `name`
+    pub fn select_as<C>(mut self, column: C, name: &str) -> Self
+    where
+        C: Column,
+    {
+        if let Some(column) = self.try_qualify(column) {
+            self.select.push(format!("{} AS {}", column.alias, name));
+        }
+        self
+    }
+
+    /// Every selectable column of every table, named `{alias}_{column}`
+    pub fn prefixed_columns(&self) -> Vec<String> {
+        self.tables
+            .iter()
+            .flat_map(|table| {
+                table.columns.iter().map(|column| {
+                    format!("{} AS {}{}", table.qualify(column), table.prefix(), column)
+                })
+            })
+            .collect()
+    }
+
+    pub fn build(
+        &self,
+        columns: &[String],
+        platform: &Platform,
+    ) -> GrapefruitResult<(String, Vec<Value>)> {
+        if let Some(error) = &self.error {
+            return Err(GrapefruitError::ObjectValidError(error.clone()));
+        }
+        let mut values = Vec::new();
+        let (first, joined) = self.tables.split_first().expect("join without table");
+        let mut sql = format!(
+            "SELECT {} FROM {} {}",
+            columns.join(","),
+            first.table_name,
+            first.alias
+        );
+        for table in joined.iter() {
+            let Some((join_type, column, join_column)) = &table.join else {
+                continue;
+            };
+            sql.push_str(&format!(
+                " {} {} {} ON {} = {}",
+                join_type.keyword(),
+                table.table_name,
+                table.alias,
+                column,
+                join_column
+            ));
+            // keep deleted rows out of the join itself, so outer joins still
+            // return the rows they have no match for
+            if let Some((logic, value)) = &table.logic_delete {
+                values.push(value.clone());
+                sql.push_str(&format!(
+                    " AND {} = {}",
+                    table.qualify(logic),
+                    platform.mark(values.len())
+                ));
+            }
+        }
+
+        let index = values.len() + 1;
+        // a right join leaves the `FROM` table's columns NULL on the rows it
+        // has no match for, which its logic delete filter must keep
+        let right_join = joined
+            .iter()
+            .any(|table| matches!(table.join, Some((JoinType::Right, _, _))));
+        let (build_sql, vals) = match &first.logic_delete {
+            Some((logic, value)) if right_join => self.wrapper.build_and_or_null(
+                platform,
+                index,
+                &first.qualify(logic),
+                value.clone(),
+            ),
+            Some((logic, value)) => {
+                self.wrapper
+                    .build_and(platform, index, &first.qualify(logic), value.clone())
+            }
+            None => self.wrapper.build(platform, index),
+        };
+        sql = format!("{} WHERE {}", sql, build_sql);
+        values.extend(vals);
+        Ok((sql, values))
+    }
+
+    fn add<C>(mut self, column: C, f: impl FnOnce(Wrapper, ColumnInfo) -> Wrapper) -> Self
+    where
+        C: Column,
+    {
+        if let Some(column) = self.try_qualify(column) {
+            self.wrapper = f(self.wrapper, column);
+        }
+        self
+    }
+
+    pub fn or(mut self) -> Self {
+        self.wrapper = self.wrapper.or();
+        self
+    }
+
+    fn add_nested_condition<F: FnOnce(Self) -> Self>(mut self, f: F) -> Self {
+        let nested = f(Self {
+            tables: self.tables.clone(),
+            select: Vec::new(),
+            wrapper: Wrapper::with_bracket_num(self.wrapper.bracket_num + 1),
+            error: None,
+        });
+        if self.error.is_none() {
+            self.error = nested.error;
+        }
+        self.wrapper = self.wrapper.do_it(
+            true,
+            SegmentType::Normal,
+            Segment::Bracket(Box::new(nested.wrapper)),
+        );
+        self
+    }
+
+    pub fn and_fn<F: FnOnce(Self) -> Self>(self, f: F) -> Self {
+        self.add_nested_condition(f)
+    }
+
+    pub fn or_fn<F: FnOnce(Self) -> Self>(self, f: F) -> Self {
+        self.or().add_nested_condition(f)
+    }
+
+    pub fn eq<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.eq(c, val))
+    }
+
+    pub fn ne<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.ne(c, val))
+    }
+
+    pub fn gt<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.gt(c, val))
+    }
+
+    pub fn ge<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.ge(c, val))
+    }
+
+    pub fn lt<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.lt(c, val))
+    }
+
+    pub fn le<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.le(c, val))
+    }
+
+    pub fn like<C, V>(self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        self.add(column, |w, c| w.like(c, val))
+    }
+
+    pub fn in_list<C, V>(self, column: C, val: Vec<V>) -> Self
+    where
+        C: Column,
+        V: Into<Value> + NotU8 + ValueType,
+    {
+        self.add(column, |w, c| w.in_list(c, val))
+    }
+
+    pub fn not_in<C, V>(self, column: C, val: Vec<V>) -> Self
+    where
+        C: Column,
+        V: Into<Value> + NotU8 + ValueType,
+    {
+        self.add(column, |w, c| w.not_in(c, val))
+    }
+
+    pub fn between<C, V>(self, column: C, val1: V, val2: V) -> Self
+    where
+        C: Column,
+        V: Into<Value> + ValueType,
+    {
+        self.add(column, |w, c| w.between(c, val1, val2))
+    }
+
+    pub fn is_null<C>(self, column: C) -> Self
+    where
+        C: Column,
+    {
+        self.add(column, |w, c| w.is_null(c))
+    }
+
+    pub fn is_not_null<C>(self, column: C) -> Self
+    where
+        C: Column,
+    {
+        self.add(column, |w, c| w.is_not_null(c))
+    }
+
+    pub fn order_by_asc<C>(self, column: C) -> Self
+    where
+        C: Column,
+    {
+        self.add(column, |w, c| w.order_by_asc(c))
+    }
+
+    pub fn order_by_desc<C>(self, column: C) -> Self
+    where
+        C: Column,
+    {
+        self.add(column, |w, c| w.order_by_desc(c))
+    }
+}
+
+/// A row of a join mapped into one value per joined table, in join order
+pub trait JoinRow: Sized + Send {
+    /// number of tables the row is made of
+    fn arity() -> usize;
+
+    fn try_get_join(result: &QueryResult, prefixes: &[String]) -> GrapefruitResult<Self>;
+}
+
+/// One table of a `JoinRow`: `T`, or `Option<T>` for the outer side of a join
+pub trait JoinPart: Sized + Send {
+    fn try_get_part(result: &QueryResult, prefix: &str) -> GrapefruitResult<Self>;
+}
+
+impl<T> JoinPart for T
+where
+    T: Entity + TryGetable,
+{
+    fn try_get_part(result: &QueryResult, prefix: &str) -> GrapefruitResult<Self> {
+        T::try_get_prefixed(result, prefix)?.ok_or_else(|| {
+            GrapefruitError::ObjectValidError(format!(
+                "join row has no {} columns, use Option<_> for an outer join",
+                T::table_name()
+            ))
+        })
+    }
+}
+
+impl<T> JoinPart for Option<T>
+where
+    T: Entity + TryGetable,
+{
+    fn try_get_part(result: &QueryResult, prefix: &str) -> GrapefruitResult<Self> {
+        T::try_get_prefixed(result, prefix)
+    }
+}
+
+macro_rules! impl_join_row {
+    ( $len: expr, $( $part: ident => $index: tt ),+ ) => {
+        impl<$( $part ),+> JoinRow for ( $( $part, )+ )
+        where
+            $( $part: JoinPart ),+
+        {
+            fn arity() -> usize {
+                $len
+            }
+
+            fn try_get_join(result: &QueryResult, prefixes: &[String]) -> GrapefruitResult<Self> {
+                Ok(( $( $part::try_get_part(result, &prefixes[$index])?, )+ ))
+            }
+        }
+    };
+}
+
+impl_join_row!(2, A => 0, B => 1);
+impl_join_row!(3, A => 0, B => 1, C => 2);
+impl_join_row!(4, A => 0, B => 1, C => 2, D => 3);
@@ -1,4 +1,5 @@
 
+mod aggregate;
 mod errors;
 #[macro_use]
 mod pool;
@@ -14,8 +15,14 @@ mod params;
 mod wrapper;
 mod segment;
 mod helper;
+mod join;
 mod page;
+mod cursor;
+mod transaction;
+#[cfg(feature = "postgres")]
+mod copy;
 
+pub use aggregate::*;
 pub use data::*;
 pub use errors::*;
 pub use grapefruit::*;
@@ -28,4 +35,7 @@ pub use params::*;
 pub use wrapper::*;
 pub use segment::*;
 pub use helper::*;
+pub use join::*;
 pub use page::*;
+pub use cursor::*;
+pub use transaction::*;
@@ -1,3 +1,5 @@
+use crate::{Entity, GrapefruitError, GrapefruitResult, OrderByType, Segment, Wrapper};
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Page<T>
 where
@@ -7,6 +9,16 @@ where
     pub page: i64,
     pub rows: i64,
     pub records: Vec<T>,
+    /// whether `total` was counted; without a count it stays 0
+    #[serde(default = "search_count_default")]
+    pub search_count: bool,
+    /// without a count: there is a row past this page
+    #[serde(default)]
+    pub more: bool,
+}
+
+fn search_count_default() -> bool {
+    true
 }
 
 impl<T> Page<T>
@@ -19,6 +31,8 @@ where
             page,
             rows,
             records: vec![],
+            search_count: true,
+            more: false,
         }
     }
 
@@ -34,15 +48,139 @@ where
         self.records.is_empty()
     }
 
-    pub fn map<R, F: FnMut(&T) -> R>(&self, mut f: F) -> Page<R> {
-        if self.is_empty() {}
-        let mut page = Page::<R>::new(self.page, self.rows);
-        let result = self
-            .records
-            .iter()
-            .map(|record| f(record))
-            .collect::<Vec<_>>();
-        page.records = result;
+    /// Number of pages, 0 when `total` was not counted
+    pub fn pages(&self) -> i64 {
+        if self.rows <= 0 {
+            return 0;
+        }
+        (self.total + self.rows - 1) / self.rows
+    }
+
+    pub fn has_next(&self) -> bool {
+        match self.search_count {
+            true => self.page < self.pages(),
+            false => self.more,
+        }
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.page > 1
+    }
+
+    pub fn map<R, F: FnMut(&T) -> R>(&self, f: F) -> Page<R> {
+        let records = self.records.iter().map(f).collect::<Vec<_>>();
+        self.with_records(records)
+    }
+
+    /// Like `map`, taking the records by value
+    pub fn into_map<R, F: FnMut(T) -> R>(self, f: F) -> Page<R> {
+        let mut page = self.with_records(Vec::new());
+        page.records = self.records.into_iter().map(f).collect();
         page
     }
+
+    fn with_records<R>(&self, records: Vec<R>) -> Page<R> {
+        Page {
+            total: self.total,
+            page: self.page,
+            rows: self.rows,
+            records,
+            search_count: self.search_count,
+            more: self.more,
+        }
+    }
+}
+
+/// Page to read with `page_by_request`, as received from an API caller
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct PageRequest {
+    /// 1-based page number
+    pub page: i64,
+    pub rows: i64,
+    /// column (field or column name) and direction, applied before the
+    /// wrapper's own `order_by`
+    #[serde(default)]
+    pub orders: Vec<(String, OrderByType)>,
+    /// count the matching rows; skipping the count saves a query when only
+    /// `has_next` is needed
+    #[serde(default = "search_count_default")]
+    pub search_count: bool,
+}
+
+impl PageRequest {
+    pub fn new(page: i64, rows: i64) -> Self {
+        Self {
+            page,
+            rows,
+            orders: Vec::new(),
+            search_count: true,
+        }
+    }
+
+    pub fn order_by(mut self, column: &str, is_asc: bool) -> Self {
+        let order = match is_asc {
+            true => OrderByType::Asc,
+            false => OrderByType::Desc,
+        };
+        self.orders.push((column.to_string(), order));
+        self
+    }
+
+    pub fn order_by_asc(self, column: &str) -> Self {
+        self.order_by(column, true)
+    }
+
+    pub fn order_by_desc(self, column: &str) -> Self {
+        self.order_by(column, false)
+    }
+
+    pub fn search_count(mut self, search_count: bool) -> Self {
+        self.search_count = search_count;
+        self
+    }
+
+    pub fn validate(&self) -> GrapefruitResult<()> {
+        if self.page < 1 || self.rows < 1 {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "page {} of {} rows, both must be at least 1",
+                self.page, self.rows
+            )));
+        }
+        Ok(())
+    }
+
+    /// `wrapper` ordered by the request's orders first. The columns come
+    /// from outside, so only columns of `T` are accepted.
+    pub fn apply<T>(&self, mut wrapper: Wrapper) -> GrapefruitResult<Wrapper>
+    where
+        T: Entity,
+    {
+        if self.orders.is_empty() {
+            return Ok(wrapper);
+        }
+        let column_map = T::columns();
+        let mut orders = Vec::with_capacity(self.orders.len());
+        for (column, order) in self.orders.iter() {
+            let info = column_map
+                .get(column)
+                .or_else(|| column_map.values().find(|c| &c.alias == column));
+            let Some(info) = info else {
+                return Err(GrapefruitError::ObjectValidError(format!(
+                    "can not order by `{}`, which is not a column of {}",
+                    column,
+                    T::table_name()
+                )));
+            };
+            orders.push((info.alias.clone(), order.clone()));
+        }
+        orders.extend(
+            wrapper
+                .expression
+                .order_by
+                .get_order_by()
+                .unwrap_or_default(),
+        );
+        wrapper.expression.order_by = Segment::OrderBy(orders);
+        Ok(wrapper)
+    }
 }
@@ -17,6 +17,20 @@ impl From<Vec<Value>> for Params {
     }
 }
 
+/// Named parameters for `query_as` and `execute_raw`,
+/// e.g. `params! { "name" => "a", "ids" => vec![1, 2] }`
+#[macro_export]
+macro_rules! params {
+    () => {
+        std::collections::HashMap::<String, $crate::Value>::new()
+    };
+    ( $( $name: expr => $value: expr ),+ $(,)? ) => {{
+        let mut map = std::collections::HashMap::<String, $crate::Value>::new();
+        $( map.insert($name.to_string(), $crate::Value::from($value)); )+
+        map
+    }};
+}
+
 #[cfg(feature = "postgres")]
 impl<'q> sqlx::IntoArguments<'q, sqlx::Postgres> for Params {
     fn into_arguments(self) -> sqlx::postgres::PgArguments {
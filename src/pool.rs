@@ -1,8 +1,9 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use crate::{
-    exec, snowflake::SnowflakeGenerator, ExecResult, IdentifierGenerator, MetaObjectHandler,
-    Params, QueryResult, QueryResults, Value,
+    exec, fetch_rows, snowflake::SnowflakeGenerator, ExecResult, IdentifierGenerator, LockMode,
+    LockWait, MetaObjectHandler, Params, PlatformTransaction, QueryResult, QueryResults, RowLock,
+    TransactionOptions, Value,
 };
 
 use url::Url;
@@ -46,10 +47,192 @@ impl Platform {
             #[cfg(feature = "postgres")]
             Platform::Postgres => format!("\"{}\"", column),
             #[cfg(feature = "sqlite")]
-            Platform::Sqlite => format!("'{}'", column),
+            Platform::Sqlite => format!("\"{}\"", column),
             _ => "".to_owned(),
         }
     }
+
+    pub fn savepoint(&self, name: &str) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => format!("SAVEPOINT `{}`", name),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => format!("SAVEPOINT \"{}\"", name),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => format!("SAVEPOINT \"{}\"", name),
+            _ => "".to_owned(),
+        }
+    }
+
+    pub fn release_savepoint(&self, name: &str) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => format!("RELEASE SAVEPOINT `{}`", name),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => format!("RELEASE SAVEPOINT \"{}\"", name),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => format!("RELEASE SAVEPOINT \"{}\"", name),
+            _ => "".to_owned(),
+        }
+    }
+
+    pub fn rollback_to_savepoint(&self, name: &str) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => format!("ROLLBACK TO SAVEPOINT `{}`", name),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => format!("ROLLBACK TO SAVEPOINT \"{}\"", name),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => format!("ROLLBACK TO SAVEPOINT \"{}\"", name),
+            _ => "".to_owned(),
+        }
+    }
+
+    /// Head of the update branch of an upsert on `conflict`
+    #[cfg_attr(
+        not(any(feature = "postgres", feature = "sqlite")),
+        allow(unused_variables)
+    )]
+    pub fn on_conflict(&self, conflict: &[String]) -> String {
+        match self {
+            // MySQL resolves the conflict against any unique index
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => "ON DUPLICATE KEY UPDATE".to_owned(),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => format!("ON CONFLICT ({}) DO UPDATE SET", conflict.join(",")),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => format!("ON CONFLICT ({}) DO UPDATE SET", conflict.join(",")),
+            _ => "".to_owned(),
+        }
+    }
+
+    /// Upsert clause keeping the stored row untouched on `conflict`
+    pub fn on_conflict_do_nothing(&self, conflict: &[String]) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => format!("ON DUPLICATE KEY UPDATE {0} = {0}", conflict[0]),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => format!("ON CONFLICT ({}) DO NOTHING", conflict.join(",")),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => format!("ON CONFLICT ({}) DO NOTHING", conflict.join(",")),
+            _ => "".to_owned(),
+        }
+    }
+
+    /// Value `column` would have had in the row that hit the conflict
+    pub fn excluded(&self, column: &str) -> String {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => format!("VALUES({})", column),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => format!("EXCLUDED.{}", column),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => format!("EXCLUDED.{}", column),
+            _ => "".to_owned(),
+        }
+    }
+
+    /// Whether the update branch of an upsert accepts a `WHERE` clause.
+    /// MySQL has none, so it can not guard an upsert by version.
+    pub fn upsert_where(&self) -> bool {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => false,
+            _ => true,
+        }
+    }
+
+    /// Row lock clause closing a SELECT
+    pub fn row_lock(&self, lock: &RowLock) -> GrapefruitResult<String> {
+        let mode = match lock.mode {
+            LockMode::Update => "FOR UPDATE",
+            LockMode::Share => "FOR SHARE",
+        };
+        let clause = match lock.wait {
+            LockWait::Wait => mode.to_string(),
+            LockWait::NoWait => format!("{} NOWAIT", mode),
+            LockWait::SkipLocked => format!("{} SKIP LOCKED", mode),
+        };
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => Ok(clause),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => Ok(clause),
+            // SQLite locks the whole database, so rows can not be claimed
+            _ => Err(GrapefruitError::PlatformError(format!(
+                "{} is not supported on {:?}",
+                clause, self
+            ))),
+        }
+    }
+
+    /// Most bind parameters a single statement may carry
+    pub fn max_bind_params(&self) -> usize {
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql => 65535,
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => 65535,
+            // SQLITE_MAX_VARIABLE_NUMBER since 3.32, 999 before
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => 32766,
+            _ => 999,
+        }
+    }
+
+    /// Clause making an INSERT hand back `columns` of the stored row,
+    /// `None` where the platform has no such clause
+    #[cfg_attr(
+        not(any(feature = "postgres", feature = "sqlite")),
+        allow(unused_variables)
+    )]
+    pub fn returning(&self, columns: &[String]) -> Option<String> {
+        match self {
+            #[cfg(feature = "postgres")]
+            Platform::Postgres => Some(format!("RETURNING {}", columns.join(","))),
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite => Some(format!("RETURNING {}", columns.join(","))),
+            _ => None,
+        }
+    }
+
+    /// Statement applying the isolation level and access mode of `options`
+    pub fn set_transaction(
+        &self,
+        options: &TransactionOptions,
+    ) -> GrapefruitResult<Option<String>> {
+        let mut characteristics = Vec::new();
+        if let Some(level) = &options.isolation_level {
+            characteristics.push(format!("ISOLATION LEVEL {}", level.as_sql()));
+        }
+        if options.read_only {
+            characteristics.push("READ ONLY".to_owned());
+        }
+        match self {
+            #[cfg(feature = "mysql")]
+            Platform::Mysql if !characteristics.is_empty() => Ok(Some(format!(
+                "SET TRANSACTION {}",
+                characteristics.join(", ")
+            ))),
+            #[cfg(feature = "postgres")]
+            Platform::Postgres if !characteristics.is_empty() => Ok(Some(format!(
+                "SET TRANSACTION {}",
+                characteristics.join(", ")
+            ))),
+            // SQLite transactions are always serializable, a level can not be chosen
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite if options.isolation_level.is_some() => {
+                Err(GrapefruitError::PlatformError(format!(
+                    "isolation level is not supported on {:?}",
+                    self
+                )))
+            }
+            // reset by the pool when the connection is released
+            #[cfg(feature = "sqlite")]
+            Platform::Sqlite if options.read_only => Ok(Some("PRAGMA query_only = ON".to_owned())),
+            _ => Ok(None),
+        }
+    }
 }
 
 impl<'a> TryFrom<&'a str> for Platform {
@@ -231,6 +414,14 @@ impl PlatformPool {
                     .acquire_timeout(options.acquire_timeout)
                     .max_lifetime(options.max_lifetime)
                     .idle_timeout(options.idle_timeout)
+                    // undo `query_only` of read-only transactions, including
+                    // ones dropped without commit or rollback
+                    .after_release(|conn, _| {
+                        Box::pin(async move {
+                            sqlx::Executor::execute(conn, "PRAGMA query_only = OFF").await?;
+                            Ok(true)
+                        })
+                    })
                     .connect(&options.url)
                     .await
                     .expect("Connect to postgres failed.");
@@ -256,6 +447,65 @@ impl PlatformPool {
         let rows: QueryResults = exec!(self, sql, params, fetch_all);
         Ok(rows)
     }
+
+    /// Send the rows of `sql` to `sender` one at a time as they arrive,
+    /// stopping early when the receiving side is dropped
+    pub async fn fetch_rows(
+        &self,
+        sql: &str,
+        params: Params,
+        sender: &RowSender,
+    ) -> GrapefruitResult<()> {
+        match self {
+            #[cfg(feature = "mysql")]
+            PlatformPool::Mysql(pool) => fetch_rows!(pool, sqlx::MySql, sql, params, sender),
+            #[cfg(feature = "postgres")]
+            PlatformPool::Postgres(pool) => fetch_rows!(pool, sqlx::Postgres, sql, params, sender),
+            #[cfg(feature = "sqlite")]
+            PlatformPool::Sqlite(pool) => fetch_rows!(pool, sqlx::Sqlite, sql, params, sender),
+        }
+        Ok(())
+    }
+
+    pub async fn begin(&self) -> GrapefruitResult<PlatformTransaction> {
+        self.begin_with(&TransactionOptions::default()).await
+    }
+
+    /// Begin a transaction with the isolation level and access mode of `options`.
+    /// MySQL only accepts `SET TRANSACTION` before the transaction starts, the
+    /// other platforms right after it.
+    pub async fn begin_with(
+        &self,
+        options: &TransactionOptions,
+    ) -> GrapefruitResult<PlatformTransaction> {
+        let transaction = match self {
+            #[cfg(feature = "mysql")]
+            PlatformPool::Mysql(pool) => {
+                let mut conn = pool.acquire().await?;
+                if let Some(sql) = Platform::Mysql.set_transaction(options)? {
+                    sqlx::Executor::execute(&mut *conn, sql.as_str()).await?;
+                }
+                PlatformTransaction::Mysql(sqlx::Transaction::begin(conn).await?)
+            }
+            #[cfg(feature = "postgres")]
+            PlatformPool::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                if let Some(sql) = Platform::Postgres.set_transaction(options)? {
+                    sqlx::Executor::execute(&mut *tx, sql.as_str()).await?;
+                }
+                PlatformTransaction::Postgres(tx)
+            }
+            #[cfg(feature = "sqlite")]
+            PlatformPool::Sqlite(pool) => {
+                let mut tx = pool.begin().await?;
+                if let Some(sql) = Platform::Sqlite.set_transaction(options)? {
+                    sqlx::Executor::execute(&mut *tx, sql.as_str()).await?;
+                }
+                PlatformTransaction::Sqlite(tx)
+            }
+        };
+        Ok(transaction)
+    }
 }
 
 #[macro_export]
@@ -287,3 +537,19 @@ macro_rules! exec {
         res
     }};
 }
+
+/// Sending half of the channel `select_stream` reads its rows from
+pub type RowSender = tokio::sync::mpsc::Sender<GrapefruitResult<QueryResult>>;
+
+#[macro_export]
+macro_rules! fetch_rows {
+    ($executor:expr, $db:ty, $sql:expr, $params:expr, $sender:expr) => {{
+        let args = <Params as sqlx::IntoArguments<'_, $db>>::into_arguments($params);
+        let mut rows = sqlx::query_with($sql, args).fetch($executor);
+        while let Some(row) = futures::TryStreamExt::try_next(&mut rows).await? {
+            if $sender.send(Ok(row.into())).await.is_err() {
+                break;
+            }
+        }
+    }};
+}
@@ -1,4 +1,12 @@
-use crate::{GrapefruitResult, MetaObject, Page, Wrapper};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use futures::stream::BoxStream;
+
+use crate::{
+    Aggregate, Cursor, CursorPage, GrapefruitResult, JoinWrapper, MetaObject, Page, PageRequest,
+    Value, Wrapper,
+};
 
 #[async_trait::async_trait]
 pub trait IdentifierGenerator: Send + Sync {
@@ -18,11 +26,38 @@ pub trait GrapefruitRepository: Send + Sync {
     where
         T: crate::Entity;
 
-    /// Insert Batch record
+    /// Insert Batch record, split into statements within the platform's
+    /// bind parameter limit and run in one transaction
     async fn insert_batch<T>(&self, entities: &[&T]) -> GrapefruitResult<u64>
     where
         T: crate::Entity;
 
+    /// Insert a record and return it as stored, with generated keys,
+    /// fill values and column defaults populated
+    async fn insert_returning<T>(&self, entity: &T) -> GrapefruitResult<T>
+    where
+        T: crate::Entity + crate::TryGetable;
+
+    /// Insert a record, or update it when it conflicts on the primary key.
+    /// A record carrying a version only updates a row of the same version;
+    /// MySQL can not tell that case apart and rejects such records. A record
+    /// without one updates the row regardless and bumps its version.
+    async fn upsert<T>(&self, entity: &T) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity;
+
+    /// Upsert Batch record on the primary key
+    async fn upsert_batch<T>(&self, entities: &[&T]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity;
+
+    /// Upsert Batch record on the `conflict` columns (the primary key when empty).
+    /// MySQL resolves conflicts against any unique index and only uses
+    /// `conflict` to keep those columns out of the update.
+    async fn upsert_batch_on<T>(&self, entities: &[&T], conflict: &[&str]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity;
+
     /// Update record by id
     async fn update_by_id<T>(&self, entity: &T) -> GrapefruitResult<u64>
     where
@@ -33,6 +68,11 @@ pub trait GrapefruitRepository: Send + Sync {
     where
         T: crate::Entity;
 
+    /// Update records matching wrapper with its SET clauses only
+    async fn update_by_set<T>(&self, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity;
+
     /// Delete by id
     async fn delete_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
     where
@@ -50,6 +90,24 @@ pub trait GrapefruitRepository: Send + Sync {
     where
         T: crate::Entity;
 
+    /// Delete by id with a DELETE statement, also for logic delete entities
+    async fn force_delete_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey;
+
+    /// Undo the logic delete of the record with id
+    async fn restore_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey;
+
+    /// Undo the logic delete of the deleted records matching wrapper,
+    /// returning how many were restored
+    async fn restore_by_wrapper<T>(&self, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity;
+
     /// Find record by id
     async fn select_by_id<T, I>(&self, id: I) -> GrapefruitResult<Option<T>>
     where
@@ -61,6 +119,37 @@ pub trait GrapefruitRepository: Send + Sync {
     where
         T: crate::Entity + crate::TryGetable;
 
+    /// Find the first record matching wrapper
+    async fn select_one<T>(&self, wrapper: Wrapper) -> GrapefruitResult<Option<T>>
+    where
+        T: crate::Entity + crate::TryGetable;
+
+    /// Find records by wrapper as maps of column name to value
+    async fn select_maps<T>(
+        &self,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<HashMap<String, Value>>>
+    where
+        T: crate::Entity;
+
+    /// Stream the records matching wrapper as they are read instead of
+    /// collecting them first. On a transaction, the transaction is busy until
+    /// the stream is finished or dropped.
+    async fn select_stream<T>(&self, wrapper: Wrapper) -> BoxStream<'static, GrapefruitResult<T>>
+    where
+        T: crate::Entity + crate::TryGetable;
+
+    /// Find records of a join, one tuple element per joined table
+    async fn select_join<R>(&self, join: JoinWrapper) -> GrapefruitResult<Vec<R>>
+    where
+        R: crate::JoinRow;
+
+    /// Find the `select` projection of a join, or every column named
+    /// `{alias}_{column}` when nothing is selected
+    async fn select_join_as<P>(&self, join: JoinWrapper) -> GrapefruitResult<Vec<P>>
+    where
+        P: crate::TryGetable + Send;
+
     /// Find all
     async fn select_all<T>(&self) -> GrapefruitResult<Vec<T>>
     where
@@ -85,6 +174,72 @@ pub trait GrapefruitRepository: Send + Sync {
     ) -> GrapefruitResult<Page<T>>
     where
         T: crate::Entity + crate::TryGetable;
+
+    /// page by request, ordered by its orders and optionally not counted
+    async fn page_by_request<T>(
+        &self,
+        request: PageRequest,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Page<T>>
+    where
+        T: crate::Entity + crate::TryGetable;
+
+    /// Keyset page of `size` records after (or before) `cursor`, ordered by
+    /// the wrapper's `order_by` and then the primary key. Unlike
+    /// `page_by_wrapper` it does not scan the skipped rows, and only counts
+    /// the matching rows when `search_count` is set.
+    async fn cursor_page<T>(
+        &self,
+        wrapper: Wrapper,
+        cursor: Option<Cursor>,
+        size: i64,
+        search_count: bool,
+    ) -> GrapefruitResult<CursorPage<T>>
+    where
+        T: crate::Entity + crate::TryGetable;
+
+    /// Run raw `sql`, binding its `:name` placeholders from `params`,
+    /// and map the rows through `TryGetable`
+    async fn query_as<T>(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<Vec<T>>
+    where
+        T: crate::TryGetable + Send;
+
+    /// Run raw `sql`, binding its `:name` placeholders from `params`,
+    /// and return the rows as maps of column name to value
+    async fn query_maps(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<Vec<HashMap<String, Value>>>;
+
+    /// Run raw `sql`, binding its `:name` placeholders from `params`,
+    /// and return the number of rows affected
+    async fn execute_raw(&self, sql: &str, params: HashMap<String, Value>)
+        -> GrapefruitResult<u64>;
+
+    /// Aggregate over the records matching wrapper, `None` when it is `NULL`
+    async fn select_aggregate<T, R>(
+        &self,
+        aggregate: Aggregate,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Option<R>>
+    where
+        T: crate::Entity,
+        R: crate::ValueType + Send;
+
+    /// Aggregates per group of the wrapper's `group_by` columns, as
+    /// (group column values, aggregate values by `Aggregate::name`)
+    async fn select_grouped<T>(
+        &self,
+        aggregates: &[Aggregate],
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<(HashMap<String, Value>, HashMap<String, Value>)>>
+    where
+        T: crate::Entity;
 }
 
 #[async_trait::async_trait]
@@ -99,76 +254,133 @@ where
         grapefruit
     }
 
+    /// `get_grapefruit`, bound to the current `transactional` scope if there is one
+    async fn executor<'a>(&'a self) -> Cow<'a, crate::Grapefruit> {
+        match crate::transactional_grapefruit() {
+            Some(grapefruit) => Cow::Owned(grapefruit),
+            None => Cow::Borrowed(self.get_grapefruit().await),
+        }
+    }
+
     /// Insert a record
     async fn insert(&self, entity: &T) -> GrapefruitResult<u64> {
-        self.get_grapefruit().await.insert(entity).await
+        self.executor().await.insert(entity).await
     }
 
     /// Insert Batch record
     async fn insert_batch(&self, entities: &[&T]) -> GrapefruitResult<u64> {
-        self.get_grapefruit().await.insert_batch(entities).await
+        self.executor().await.insert_batch(entities).await
+    }
+
+    /// Insert a record and return it as stored
+    async fn insert_returning(&self, entity: &T) -> GrapefruitResult<T> {
+        self.executor().await.insert_returning(entity).await
+    }
+
+    /// Insert a record, or update it when it conflicts on the primary key
+    async fn upsert(&self, entity: &T) -> GrapefruitResult<u64> {
+        self.executor().await.upsert(entity).await
+    }
+
+    /// Upsert Batch record on the primary key
+    async fn upsert_batch(&self, entities: &[&T]) -> GrapefruitResult<u64> {
+        self.executor().await.upsert_batch(entities).await
+    }
+
+    /// Upsert Batch record on the `conflict` columns
+    async fn upsert_batch_on(&self, entities: &[&T], conflict: &[&str]) -> GrapefruitResult<u64> {
+        self.executor()
+            .await
+            .upsert_batch_on(entities, conflict)
+            .await
     }
 
     /// Update record by id
     async fn update_by_id(&self, entity: &T) -> GrapefruitResult<u64> {
-        self.get_grapefruit().await.update_by_id(entity).await
+        self.executor().await.update_by_id(entity).await
     }
 
     /// Update record by Wrapper
     async fn update_by_wrapper(&self, entity: &T, wrapper: Wrapper) -> GrapefruitResult<u64> {
-        self.get_grapefruit()
+        self.executor()
             .await
             .update_by_wrapper(entity, wrapper)
             .await
     }
 
+    /// Update records matching wrapper with its SET clauses only
+    async fn update_by_set(&self, wrapper: Wrapper) -> GrapefruitResult<u64> {
+        self.executor().await.update_by_set::<T>(wrapper).await
+    }
+
     /// Delete by id
     async fn delete_by_id(&self, id: I) -> GrapefruitResult<bool> {
-        self.get_grapefruit().await.delete_by_id::<T, I>(id).await
+        self.executor().await.delete_by_id::<T, I>(id).await
     }
 
     /// Delete by ids
     async fn delete_by_ids(&self, ids: &[I]) -> GrapefruitResult<bool> {
-        self.get_grapefruit().await.delete_by_ids::<T, I>(ids).await
+        self.executor().await.delete_by_ids::<T, I>(ids).await
     }
 
     /// Delete by Wrapper
     async fn delete_by_wrapper(&self, wrapper: Wrapper) -> GrapefruitResult<bool> {
-        self.get_grapefruit()
-            .await
-            .delete_by_wrapper::<T>(wrapper)
-            .await
+        self.executor().await.delete_by_wrapper::<T>(wrapper).await
+    }
+
+    /// Delete by id with a DELETE statement, also for logic delete entities
+    async fn force_delete_by_id(&self, id: I) -> GrapefruitResult<bool> {
+        self.executor().await.force_delete_by_id::<T, I>(id).await
+    }
+
+    /// Undo the logic delete of the record with id
+    async fn restore_by_id(&self, id: I) -> GrapefruitResult<bool> {
+        self.executor().await.restore_by_id::<T, I>(id).await
+    }
+
+    /// Undo the logic delete of the deleted records matching wrapper
+    async fn restore_by_wrapper(&self, wrapper: Wrapper) -> GrapefruitResult<u64> {
+        self.executor().await.restore_by_wrapper::<T>(wrapper).await
     }
 
     /// Find record by id
     async fn select_by_id(&self, id: I) -> GrapefruitResult<Option<T>> {
-        self.get_grapefruit().await.select_by_id::<T, I>(id).await
+        self.executor().await.select_by_id::<T, I>(id).await
     }
 
     /// Find record by wrapper
     async fn select_by_wrapper(&self, wrapper: Wrapper) -> GrapefruitResult<Vec<T>> {
-        self.get_grapefruit()
-            .await
-            .select_by_wrapper::<T>(wrapper)
-            .await
+        self.executor().await.select_by_wrapper::<T>(wrapper).await
+    }
+
+    /// Find the first record matching wrapper
+    async fn select_one(&self, wrapper: Wrapper) -> GrapefruitResult<Option<T>> {
+        self.executor().await.select_one::<T>(wrapper).await
+    }
+
+    /// Find records by wrapper as maps of column name to value
+    async fn select_maps(&self, wrapper: Wrapper) -> GrapefruitResult<Vec<HashMap<String, Value>>> {
+        self.executor().await.select_maps::<T>(wrapper).await
+    }
+
+    /// Stream the records matching wrapper as they are read
+    async fn select_stream(&self, wrapper: Wrapper) -> BoxStream<'static, GrapefruitResult<T>> {
+        self.executor().await.select_stream::<T>(wrapper).await
     }
 
     /// Find all
     async fn select_all(&self) -> GrapefruitResult<Vec<T>> {
-        self.get_grapefruit().await.select_all::<T>().await
+        self.executor().await.select_all::<T>().await
     }
 
     /// count all
     async fn count_all(&self) -> GrapefruitResult<i64> {
-        self.get_grapefruit().await.count_all::<T>().await
+        self.executor().await.count_all::<T>().await
     }
 
     /// count by wrapper
     async fn count_by_wrapper(&self, wrapper: Wrapper) -> GrapefruitResult<i64> {
-        self.get_grapefruit()
-            .await
-            .count_by_wrapper::<T>(wrapper)
-            .await
+        self.executor().await.count_by_wrapper::<T>(wrapper).await
     }
 
     /// page by wrapper
@@ -178,9 +390,62 @@ where
         row: i64,
         wrapper: Wrapper,
     ) -> GrapefruitResult<Page<T>> {
-        self.get_grapefruit()
+        self.executor()
             .await
             .page_by_wrapper::<T>(page, row, wrapper)
             .await
     }
+
+    /// page by request
+    async fn page_by_request(
+        &self,
+        request: PageRequest,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Page<T>> {
+        self.executor()
+            .await
+            .page_by_request::<T>(request, wrapper)
+            .await
+    }
+
+    /// Keyset page of `size` records after (or before) `cursor`
+    async fn cursor_page(
+        &self,
+        wrapper: Wrapper,
+        cursor: Option<Cursor>,
+        size: i64,
+        search_count: bool,
+    ) -> GrapefruitResult<CursorPage<T>> {
+        self.executor()
+            .await
+            .cursor_page::<T>(wrapper, cursor, size, search_count)
+            .await
+    }
+
+    /// Aggregate over the records matching wrapper
+    async fn select_aggregate<R>(
+        &self,
+        aggregate: Aggregate,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Option<R>>
+    where
+        R: crate::ValueType + Send,
+    {
+        self.executor()
+            .await
+            .select_aggregate::<T, R>(aggregate, wrapper)
+            .await
+    }
+
+    /// Aggregates per group of the wrapper's `group_by` columns
+    async fn select_grouped(
+        &self,
+        aggregates: &[Aggregate],
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<(HashMap<String, Value>, HashMap<String, Value>)>> {
+        self.executor()
+            .await
+            .select_grouped::<T>(aggregates, wrapper)
+            .await
+    }
 }
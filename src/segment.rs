@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::{Value, Wrapper};
+use crate::{logic_delete_filter, Entity, Value, Wrapper};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum SegmentType {
@@ -31,15 +31,64 @@ pub enum Segment {
     IsNull(String),
     IsNotNull(String),
     GroupBy(Vec<String>),
-    Having(Vec<String>),
+    /// SQL with `{0}`, `{1}`, ... placeholders for the values bound to it
+    Having(Vec<(String, Vec<Value>)>),
     Bracket(Box<Wrapper>),
     OrderBy(Vec<(String, OrderByType)>),
-    // EXISTS,
+    Exists(SubQuery),
+    NotExists(SubQuery),
+    InSub(String, SubQuery),
+    NotInSub(String, SubQuery),
+    /// `column = outer column`, both qualified with their table
+    Correlate(String, String),
     Between(String, Value, Value),
     NotBetween(String, Value, Value),
 }
 
+/// `SELECT` on another entity used inside a condition
 #[derive(Clone, Debug, PartialEq)]
+pub struct SubQuery {
+    pub select: String,
+    pub table: String,
+    pub wrapper: Box<Wrapper>,
+    pub logic_delete: Option<(String, Value)>,
+}
+
+impl SubQuery {
+    pub fn new<T>(select: &str, wrapper: Wrapper) -> Self
+    where
+        T: Entity,
+    {
+        let logic_delete = logic_delete_filter::<T>(&wrapper.logic_delete);
+        Self {
+            select: select.to_string(),
+            table: T::table_name(),
+            wrapper: Box::new(wrapper),
+            logic_delete,
+        }
+    }
+
+    /// SQL of the subquery, its params named with `prefix` so they can not
+    /// clash with the ones of the outer wrapper
+    fn get_sql(&self, prefix: &str) -> (String, HashMap<String, Value>) {
+        let mut wrapper = match &self.logic_delete {
+            Some(condition) => self.wrapper.and_all(vec![condition.clone()]),
+            None => self.wrapper.as_ref().clone(),
+        };
+        wrapper.expression.set_prefix(prefix);
+        let (mut where_sql, params) = wrapper.get_sql();
+        if wrapper.expression.normal.is_empty() {
+            where_sql = format!("1 = 1 {}", where_sql);
+        }
+        let sql = format!(
+            "select {} from {} where {}",
+            self.select, self.table, where_sql
+        );
+        (sql, params)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum OrderByType {
     Asc,
     Desc,
@@ -54,6 +103,35 @@ impl OrderByType {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum LockMode {
+    Update,
+    Share,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LockWait {
+    Wait,
+    NoWait,
+    SkipLocked,
+}
+
+/// Row lock taken by a SELECT, e.g. `FOR UPDATE SKIP LOCKED`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowLock {
+    pub mode: LockMode,
+    pub wait: LockWait,
+}
+
+/// Rows of a logic delete entity a wrapper applies to
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum LogicDeleteScope {
+    #[default]
+    NotDeleted,
+    All,
+    Deleted,
+}
+
 impl Segment {
     pub fn add_group_by(&mut self, v: &Vec<String>) {
         match self {
@@ -62,7 +140,7 @@ impl Segment {
         }
     }
 
-    pub fn add_having(&mut self, v: &Vec<String>) {
+    pub fn add_having(&mut self, v: &Vec<(String, Vec<Value>)>) {
         match self {
             Segment::Having(cols) => cols.extend_from_slice(v),
             _ => panic!("Segment is not Having"),
@@ -83,7 +161,7 @@ impl Segment {
         }
     }
 
-    pub fn get_having(&self) -> Option<Vec<String>> {
+    pub fn get_having(&self) -> Option<Vec<(String, Vec<Value>)>> {
         match self {
             Segment::Having(cols) => Some(cols.clone()),
             _ => None,
@@ -101,6 +179,8 @@ impl Segment {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Segments {
     pub bracket_num: i32,
+    /// prefix of the param names, set on the wrapper of a subquery
+    pub prefix: String,
     pub normal: Vec<Segment>,
     pub group_by: Segment,
     pub order_by: Segment,
@@ -111,6 +191,7 @@ impl Segments {
     pub fn with_bracket_num(bracket_num: i32) -> Self {
         Self {
             bracket_num: bracket_num,
+            prefix: String::new(),
             normal: Vec::new(),
             group_by: Segment::GroupBy(vec![]),
             order_by: Segment::OrderBy(vec![]),
@@ -138,8 +219,18 @@ impl Segments {
         }
     }
 
+    /// Prefix the param names of these segments and their brackets
+    fn set_prefix(&mut self, prefix: &str) {
+        self.prefix = format!("{}_", prefix);
+        for segment in self.normal.iter_mut() {
+            if let Segment::Bracket(w) = segment {
+                w.expression.set_prefix(prefix);
+            }
+        }
+    }
+
     fn format_col_name(&self, index: usize, col: &str) -> String {
-        format!("{}_{}_{}", self.bracket_num, index, col)
+        format!("{}{}_{}_{}", self.prefix, self.bracket_num, index, col)
     }
 
     fn get_normal_sql(&self) -> (String, HashMap<String, Value>) {
@@ -247,6 +338,29 @@ impl Segments {
                     sql.push_str(&format!("( {} )", s));
                     params.extend(p);
                 }
+                Segment::Exists(sub) => {
+                    let (s, p) = sub.get_sql(&self.format_col_name(index, "exists"));
+                    sql.push_str(&format!("exists ( {} )", s));
+                    params.extend(p);
+                }
+                Segment::NotExists(sub) => {
+                    let (s, p) = sub.get_sql(&self.format_col_name(index, "exists"));
+                    sql.push_str(&format!("not exists ( {} )", s));
+                    params.extend(p);
+                }
+                Segment::InSub(col, sub) => {
+                    let (s, p) = sub.get_sql(&self.format_col_name(index, col));
+                    sql.push_str(&format!("{} in ( {} )", col, s));
+                    params.extend(p);
+                }
+                Segment::NotInSub(col, sub) => {
+                    let (s, p) = sub.get_sql(&self.format_col_name(index, col));
+                    sql.push_str(&format!("{} not in ( {} )", col, s));
+                    params.extend(p);
+                }
+                Segment::Correlate(col, outer) => {
+                    sql.push_str(&format!("{} = {}", col, outer));
+                }
                 Segment::Between(col, val1, val2) => {
                     let col_name = self.format_col_name(index, col);
                     let startcol = format!("{}_start", col_name);
@@ -283,15 +397,25 @@ impl Segments {
         }
     }
 
-    fn get_having_sql(&self) -> String {
-        if let Some(v) = self.having.get_having() {
-            if v.is_empty() {
-                return "".to_string();
+    fn get_having_sql(&self) -> (String, HashMap<String, Value>) {
+        let mut params = HashMap::new();
+        let Some(v) = self.having.get_having() else {
+            return ("".to_string(), params);
+        };
+        if v.is_empty() {
+            return ("".to_string(), params);
+        }
+        let mut sqls = Vec::with_capacity(v.len());
+        for (index, (sql, vals)) in v.into_iter().enumerate() {
+            let mut sql = sql;
+            for (i, val) in vals.into_iter().enumerate() {
+                let name = format!("{}{}_having_{}_{}", self.prefix, self.bracket_num, index, i);
+                sql = sql.replace(&format!("{{{}}}", i), &format!(" :{} ", name));
+                params.insert(name, val);
             }
-            format!("having {}", v.join(" and "))
-        } else {
-            "".to_string()
+            sqls.push(sql);
         }
+        (format!("having {}", sqls.join(" and ")), params)
     }
 
     fn get_order_by_sql(&self) -> String {
@@ -310,9 +434,10 @@ impl Segments {
     }
 
     pub fn get_sql(&self) -> (String, HashMap<String, Value>) {
-        let (normal_sql, val) = self.get_normal_sql();
+        let (normal_sql, mut val) = self.get_normal_sql();
         let group_by = self.get_group_by_sql();
-        let having = self.get_having_sql();
+        let (having, having_val) = self.get_having_sql();
+        val.extend(having_val);
         let order_by = self.get_order_by_sql();
         let sql = format!("{} {} {} {}", normal_sql, group_by, having, order_by);
         (sql, val)
@@ -1,45 +1,810 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    ops::Deref,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::{stream::BoxStream, FutureExt};
+use tokio::sync::Mutex;
+
+use crate::{
+    exec_tx, Aggregate, Cursor, CursorPage, ExecResult, Grapefruit, GrapefruitError,
+    GrapefruitRepository, GrapefruitResult, JoinWrapper, Page, PageRequest, Params, PlatformPool,
+    QueryResult, QueryResults, RowSender, Value, Wrapper,
+};
+
+/// Transaction shared between a `Grapefruit` handle and the scope that owns it.
+/// `None` once the transaction has been committed or rolled back.
+pub(crate) type SharedTransaction = Arc<Mutex<Option<PlatformTransaction>>>;
+
+/// Position of a `Grapefruit` handle inside a transaction.
+/// Depth `0` is the outermost transaction, deeper levels are savepoints.
+#[derive(Clone)]
+pub(crate) struct TransactionContext {
+    pub(crate) transaction: SharedTransaction,
+    pub(crate) depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+/// Re-run a transaction that failed with a serialization failure or deadlock
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub(crate) max_retries: u32,
+    pub(crate) backoff: Duration,
+    pub(crate) max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Delay before retry number `attempt`, doubling from `backoff`
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransactionOptions {
+    pub(crate) isolation_level: Option<IsolationLevel>,
+    pub(crate) read_only: bool,
+    pub(crate) retry: Option<RetryPolicy>,
+}
+
+impl TransactionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_isolation_level(mut self, isolation_level: IsolationLevel) -> Self {
+        self.isolation_level = Some(isolation_level);
+        self
+    }
+
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn has_characteristics(&self) -> bool {
+        self.isolation_level.is_some() || self.read_only
+    }
+}
+
+#[derive(Debug)]
+pub enum PlatformTransaction {
+    #[cfg(feature = "mysql")]
+    Mysql(sqlx::Transaction<'static, sqlx::MySql>),
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::Transaction<'static, sqlx::Postgres>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlx::Transaction<'static, sqlx::Sqlite>),
+}
+
+impl PlatformTransaction {
+    pub async fn execute(&mut self, sql: &str, params: Params) -> GrapefruitResult<ExecResult> {
+        let row: ExecResult = exec_tx!(self, sql, params, execute);
+        Ok(row)
+    }
+
+    pub async fn fetch_one(&mut self, sql: &str, params: Params) -> GrapefruitResult<QueryResult> {
+        let row: QueryResult = exec_tx!(self, sql, params, fetch_optional);
+        Ok(row)
+    }
+
+    pub async fn fetch_all(&mut self, sql: &str, params: Params) -> GrapefruitResult<QueryResults> {
+        let rows: QueryResults = exec_tx!(self, sql, params, fetch_all);
+        Ok(rows)
+    }
+
+    /// Send the rows of `sql` to `sender` one at a time as they arrive,
+    /// stopping early when the receiving side is dropped
+    pub async fn fetch_rows(
+        &mut self,
+        sql: &str,
+        params: Params,
+        sender: &RowSender,
+    ) -> GrapefruitResult<()> {
+        match self {
+            #[cfg(feature = "mysql")]
+            PlatformTransaction::Mysql(tx) => {
+                fetch_rows!(&mut **tx, sqlx::MySql, sql, params, sender)
+            }
+            #[cfg(feature = "postgres")]
+            PlatformTransaction::Postgres(tx) => {
+                fetch_rows!(&mut **tx, sqlx::Postgres, sql, params, sender)
+            }
+            #[cfg(feature = "sqlite")]
+            PlatformTransaction::Sqlite(tx) => {
+                fetch_rows!(&mut **tx, sqlx::Sqlite, sql, params, sender)
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute a statement without preparing it, e.g. transaction control.
+    pub async fn execute_unprepared(&mut self, sql: &str) -> GrapefruitResult<()> {
+        match self {
+            #[cfg(feature = "mysql")]
+            PlatformTransaction::Mysql(tx) => {
+                sqlx::Executor::execute(&mut **tx, sql).await?;
+            }
+            #[cfg(feature = "postgres")]
+            PlatformTransaction::Postgres(tx) => {
+                sqlx::Executor::execute(&mut **tx, sql).await?;
+            }
+            #[cfg(feature = "sqlite")]
+            PlatformTransaction::Sqlite(tx) => {
+                sqlx::Executor::execute(&mut **tx, sql).await?;
+            }
+        };
+        Ok(())
+    }
+
+    pub async fn commit(self) -> GrapefruitResult<()> {
+        match self {
+            #[cfg(feature = "mysql")]
+            PlatformTransaction::Mysql(tx) => tx.commit().await?,
+            #[cfg(feature = "postgres")]
+            PlatformTransaction::Postgres(tx) => tx.commit().await?,
+            #[cfg(feature = "sqlite")]
+            PlatformTransaction::Sqlite(tx) => tx.commit().await?,
+        };
+        Ok(())
+    }
+
+    pub async fn rollback(self) -> GrapefruitResult<()> {
+        match self {
+            #[cfg(feature = "mysql")]
+            PlatformTransaction::Mysql(tx) => tx.rollback().await?,
+            #[cfg(feature = "postgres")]
+            PlatformTransaction::Postgres(tx) => tx.rollback().await?,
+            #[cfg(feature = "sqlite")]
+            PlatformTransaction::Sqlite(tx) => tx.rollback().await?,
+        };
+        Ok(())
+    }
+}
 
 pub struct TransactionManager<'a> {
-    pool: &'a PgPool,
+    grapefruit: &'a Grapefruit,
 }
 
 impl<'a> TransactionManager<'a> {
-    pub fn new(pool: &'a PgPool) -> Self {
-        TransactionManager { pool }
+    pub fn new(grapefruit: &'a Grapefruit) -> Self {
+        TransactionManager { grapefruit }
     }
 
-    pub async fn begin(&self) -> sqlx::Result<Transaction<'a, sqlx::Postgres>> {
-        self.pool.begin().await
+    pub async fn begin(&self) -> GrapefruitResult<GrapefruitTx> {
+        self.grapefruit.begin().await
+    }
+
+    /// Run `f` inside a transaction.
+    ///
+    /// The closure receives a `GrapefruitTx`, so every repository call made
+    /// through it runs on the transaction's connection. The transaction is
+    /// committed when `f` returns `Ok` and rolled back when it returns `Err`
+    /// or panics, unless the closure already finished it itself.
+    pub async fn with_transaction<R, F, Fut>(&self, f: F) -> GrapefruitResult<R>
+    where
+        F: FnOnce(GrapefruitTx) -> Fut,
+        Fut: Future<Output = GrapefruitResult<R>>,
+    {
+        let tx = self.begin().await?;
+        let result = AssertUnwindSafe(f(tx.clone())).catch_unwind().await;
+        tx.complete(result).await
     }
 
-    pub async fn with_transaction<T, F>(&self, f: F) -> sqlx::Result<T>
+    /// Run `f` inside a transaction configured by `options`.
+    ///
+    /// With a retry policy, a transaction that fails with a serialization
+    /// failure or deadlock is rolled back and `f` runs again after a backoff.
+    pub async fn with_transaction_options<R, F, Fut>(
+        &self,
+        options: &TransactionOptions,
+        f: F,
+    ) -> GrapefruitResult<R>
     where
-        F: FnOnce(Transaction<'a, sqlx::Postgres>) -> sqlx::Result<T>,
+        F: Fn(GrapefruitTx) -> Fut,
+        Fut: Future<Output = GrapefruitResult<R>>,
     {
-        let mut transaction = self.pool.begin().await?;
+        let mut attempt = 0;
+        loop {
+            let tx = self.grapefruit.begin_with(options).await?;
+            let result = AssertUnwindSafe(f(tx.clone())).catch_unwind().await;
+            let result = tx.complete(result).await;
+            match (&result, &options.retry) {
+                (Err(e), Some(retry)) if e.is_retryable() && attempt < retry.max_retries => {
+                    tokio::time::sleep(retry.delay(attempt)).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+tokio::task_local! {
+    static CURRENT_TRANSACTION: GrapefruitTx;
+}
+
+/// How a `transactional` scope relates to a transaction that is already active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    /// Join the active transaction, or begin a new one
+    Required,
+    /// Always begin a new, independent transaction
+    RequiresNew,
+    /// Open a savepoint inside the active transaction, or begin a new one
+    Nested,
+}
 
-        let result = f(transaction.transaction()).await;
+/// Run `future` inside a transaction on the global `GRAPEFRUIT`.
+///
+/// While the future runs, the transaction is stored in a task-local, and every
+/// `BaseRepository` default method uses it automatically. A nested scope joins
+/// the active transaction. The task-local does not follow work moved to other
+/// tasks with `tokio::spawn`.
+pub async fn transactional<R, Fut>(future: Fut) -> GrapefruitResult<R>
+where
+    Fut: Future<Output = GrapefruitResult<R>>,
+{
+    transactional_with(Propagation::Required, future).await
+}
+
+/// Run `future` inside a transaction, with the given propagation
+pub async fn transactional_with<R, Fut>(
+    propagation: Propagation,
+    future: Fut,
+) -> GrapefruitResult<R>
+where
+    Fut: Future<Output = GrapefruitResult<R>>,
+{
+    let current = current_transaction();
+    let tx = match (propagation, current) {
+        (Propagation::Required, Some(_)) => return future.await,
+        (Propagation::Nested, Some(current)) => current.begin().await?,
+        _ => global_grapefruit().begin().await?,
+    };
+    let result = AssertUnwindSafe(CURRENT_TRANSACTION.scope(tx.clone(), future))
+        .catch_unwind()
+        .await;
+    tx.complete(result).await
+}
+
+/// Transaction of the enclosing `transactional` scope, if any
+pub fn current_transaction() -> Option<GrapefruitTx> {
+    CURRENT_TRANSACTION.try_with(|tx| tx.clone()).ok()
+}
+
+/// `Grapefruit` bound to the enclosing `transactional` scope, if any
+pub(crate) fn transactional_grapefruit() -> Option<Grapefruit> {
+    current_transaction().map(|tx| tx.grapefruit)
+}
 
-        if result.is_ok() {
-            transaction.commit().await?;
-        } else {
-            transaction.rollback().await?;
+fn global_grapefruit() -> &'static Grapefruit {
+    crate::GRAPEFRUIT.get().expect("Grapefruit not init")
+}
+
+/// Repository executor bound to a live transaction.
+///
+/// Clones share the same transaction; `commit` or `rollback` on any of them
+/// finishes it for all. Calling `begin` on a handle that is already inside a
+/// transaction opens a savepoint, so nested scopes only undo their own work.
+#[derive(Clone)]
+pub struct GrapefruitTx {
+    grapefruit: Grapefruit,
+    context: TransactionContext,
+    finished: Arc<AtomicBool>,
+}
+
+impl GrapefruitTx {
+    pub(crate) fn new(grapefruit: &Grapefruit, transaction: PlatformTransaction) -> Self {
+        let context = TransactionContext {
+            transaction: Arc::new(Mutex::new(Some(transaction))),
+            depth: 0,
+        };
+        GrapefruitTx {
+            grapefruit: grapefruit.with_transaction(context.clone()),
+            context,
+            finished: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) async fn savepoint(
+        grapefruit: &Grapefruit,
+        parent: &TransactionContext,
+        options: &TransactionOptions,
+    ) -> GrapefruitResult<Self> {
+        if options.has_characteristics() {
+            return Err(GrapefruitError::TransactionError(
+                "isolation level and read-only can only be set on the outermost transaction"
+                    .to_string(),
+            ));
         }
+        let context = TransactionContext {
+            transaction: parent.transaction.clone(),
+            depth: parent.depth + 1,
+        };
+        let tx = GrapefruitTx {
+            grapefruit: grapefruit.with_transaction(context.clone()),
+            context,
+            finished: Arc::new(AtomicBool::new(false)),
+        };
+        let sql = tx.grapefruit.platform().savepoint(&tx.savepoint_name());
+        tx.execute_unprepared(&sql).await?;
+        Ok(tx)
+    }
 
-        result
+    /// `Grapefruit` bound to this transaction
+    pub fn grapefruit(&self) -> &Grapefruit {
+        &self.grapefruit
+    }
+
+    /// Nesting level, `0` for the outermost transaction
+    pub fn depth(&self) -> usize {
+        self.context.depth
+    }
+
+    /// Open a nested scope backed by a savepoint
+    pub async fn begin(&self) -> GrapefruitResult<GrapefruitTx> {
+        self.grapefruit.begin().await
+    }
+
+    pub async fn is_active(&self) -> bool {
+        !self.finished.load(Ordering::SeqCst) && self.context.transaction.lock().await.is_some()
+    }
+
+    pub async fn commit(self) -> GrapefruitResult<()> {
+        if self.context.depth == 0 {
+            return self.take().await?.commit().await;
+        }
+        self.finish()?;
+        let sql = self
+            .grapefruit
+            .platform()
+            .release_savepoint(&self.savepoint_name());
+        self.execute_unprepared(&sql).await
+    }
+
+    pub async fn rollback(self) -> GrapefruitResult<()> {
+        if self.context.depth == 0 {
+            return self.take().await?.rollback().await;
+        }
+        self.finish()?;
+        let platform = self.grapefruit.platform();
+        let name = self.savepoint_name();
+        self.execute_unprepared(&platform.rollback_to_savepoint(&name))
+            .await?;
+        self.execute_unprepared(&platform.release_savepoint(&name))
+            .await
+    }
+
+    /// Commit or roll back depending on how the scope ended.
+    /// Does nothing to a transaction the scope already finished itself.
+    async fn complete<R>(
+        &self,
+        result: std::thread::Result<GrapefruitResult<R>>,
+    ) -> GrapefruitResult<R> {
+        if !self.is_active().await {
+            return result.unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        }
+        match result {
+            Ok(Ok(value)) => {
+                self.clone().commit().await?;
+                Ok(value)
+            }
+            Ok(Err(e)) => {
+                // the closure's error is the one worth reporting
+                self.clone().rollback().await.ok();
+                Err(e)
+            }
+            Err(panic) => {
+                self.clone().rollback().await.ok();
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+
+    fn savepoint_name(&self) -> String {
+        format!("grapefruit_savepoint_{}", self.context.depth)
+    }
+
+    fn finish(&self) -> GrapefruitResult<()> {
+        if self.finished.swap(true, Ordering::SeqCst) {
+            return Err(transaction_finished());
+        }
+        Ok(())
+    }
+
+    async fn take(&self) -> GrapefruitResult<PlatformTransaction> {
+        self.finish()?;
+        self.context
+            .transaction
+            .lock()
+            .await
+            .take()
+            .ok_or_else(transaction_finished)
+    }
+
+    async fn execute_unprepared(&self, sql: &str) -> GrapefruitResult<()> {
+        match self.context.transaction.lock().await.as_mut() {
+            Some(tx) => tx.execute_unprepared(sql).await,
+            None => Err(transaction_finished()),
+        }
     }
 }
 
+#[async_trait::async_trait]
+impl GrapefruitRepository for GrapefruitTx {
+    async fn insert<T>(&self, entity: &T) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.insert(entity).await
+    }
+
+    async fn insert_batch<T>(&self, entities: &[&T]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.insert_batch(entities).await
+    }
+
+    async fn insert_returning<T>(&self, entity: &T) -> GrapefruitResult<T>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit.insert_returning(entity).await
+    }
+
+    async fn upsert<T>(&self, entity: &T) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.upsert(entity).await
+    }
+
+    async fn upsert_batch<T>(&self, entities: &[&T]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.upsert_batch(entities).await
+    }
+
+    async fn upsert_batch_on<T>(&self, entities: &[&T], conflict: &[&str]) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.upsert_batch_on(entities, conflict).await
+    }
+
+    async fn update_by_id<T>(&self, entity: &T) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.update_by_id(entity).await
+    }
+
+    async fn update_by_wrapper<T>(&self, entity: &T, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.update_by_wrapper(entity, wrapper).await
+    }
+
+    async fn update_by_set<T>(&self, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.update_by_set::<T>(wrapper).await
+    }
+
+    async fn delete_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey,
+    {
+        self.grapefruit.delete_by_id::<T, I>(id).await
+    }
+
+    async fn delete_by_ids<T, I>(&self, ids: &[I]) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey,
+    {
+        self.grapefruit.delete_by_ids::<T, I>(ids).await
+    }
+
+    async fn delete_by_wrapper<T>(&self, wrapper: Wrapper) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.delete_by_wrapper::<T>(wrapper).await
+    }
+
+    async fn force_delete_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey,
+    {
+        self.grapefruit.force_delete_by_id::<T, I>(id).await
+    }
+
+    async fn restore_by_id<T, I>(&self, id: I) -> GrapefruitResult<bool>
+    where
+        T: crate::Entity,
+        I: crate::PrimaryKey,
+    {
+        self.grapefruit.restore_by_id::<T, I>(id).await
+    }
+
+    async fn restore_by_wrapper<T>(&self, wrapper: Wrapper) -> GrapefruitResult<u64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.restore_by_wrapper::<T>(wrapper).await
+    }
+
+    async fn select_by_id<T, I>(&self, id: I) -> GrapefruitResult<Option<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+        I: crate::PrimaryKey,
+    {
+        self.grapefruit.select_by_id::<T, I>(id).await
+    }
+
+    async fn select_by_wrapper<T>(&self, wrapper: Wrapper) -> GrapefruitResult<Vec<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit.select_by_wrapper::<T>(wrapper).await
+    }
+
+    async fn select_one<T>(&self, wrapper: Wrapper) -> GrapefruitResult<Option<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit.select_one(wrapper).await
+    }
+
+    async fn select_maps<T>(
+        &self,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<HashMap<String, Value>>>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.select_maps::<T>(wrapper).await
+    }
+
+    async fn select_stream<T>(&self, wrapper: Wrapper) -> BoxStream<'static, GrapefruitResult<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit.select_stream::<T>(wrapper).await
+    }
+
+    async fn select_join<R>(&self, join: JoinWrapper) -> GrapefruitResult<Vec<R>>
+    where
+        R: crate::JoinRow,
+    {
+        self.grapefruit.select_join(join).await
+    }
+
+    async fn select_join_as<P>(&self, join: JoinWrapper) -> GrapefruitResult<Vec<P>>
+    where
+        P: crate::TryGetable + Send,
+    {
+        self.grapefruit.select_join_as(join).await
+    }
+
+    async fn select_all<T>(&self) -> GrapefruitResult<Vec<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit.select_all::<T>().await
+    }
+
+    async fn count_all<T>(&self) -> GrapefruitResult<i64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.count_all::<T>().await
+    }
+
+    async fn count_by_wrapper<T>(&self, wrapper: Wrapper) -> GrapefruitResult<i64>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit.count_by_wrapper::<T>(wrapper).await
+    }
+
+    async fn page_by_wrapper<T>(
+        &self,
+        page: i64,
+        row: i64,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Page<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit
+            .page_by_wrapper::<T>(page, row, wrapper)
+            .await
+    }
+
+    async fn page_by_request<T>(
+        &self,
+        request: PageRequest,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Page<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit.page_by_request::<T>(request, wrapper).await
+    }
+
+    async fn cursor_page<T>(
+        &self,
+        wrapper: Wrapper,
+        cursor: Option<Cursor>,
+        size: i64,
+        search_count: bool,
+    ) -> GrapefruitResult<CursorPage<T>>
+    where
+        T: crate::Entity + crate::TryGetable,
+    {
+        self.grapefruit
+            .cursor_page::<T>(wrapper, cursor, size, search_count)
+            .await
+    }
+
+    async fn query_as<T>(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<Vec<T>>
+    where
+        T: crate::TryGetable + Send,
+    {
+        self.grapefruit.query_as(sql, params).await
+    }
+
+    async fn query_maps(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<Vec<HashMap<String, Value>>> {
+        self.grapefruit.query_maps(sql, params).await
+    }
+
+    async fn execute_raw(
+        &self,
+        sql: &str,
+        params: HashMap<String, Value>,
+    ) -> GrapefruitResult<u64> {
+        self.grapefruit.execute_raw(sql, params).await
+    }
+
+    async fn select_aggregate<T, R>(
+        &self,
+        aggregate: Aggregate,
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Option<R>>
+    where
+        T: crate::Entity,
+        R: crate::ValueType + Send,
+    {
+        self.grapefruit
+            .select_aggregate::<T, R>(aggregate, wrapper)
+            .await
+    }
+
+    async fn select_grouped<T>(
+        &self,
+        aggregates: &[Aggregate],
+        wrapper: Wrapper,
+    ) -> GrapefruitResult<Vec<(HashMap<String, Value>, HashMap<String, Value>)>>
+    where
+        T: crate::Entity,
+    {
+        self.grapefruit
+            .select_grouped::<T>(aggregates, wrapper)
+            .await
+    }
+}
+
+pub(crate) fn transaction_finished() -> GrapefruitError {
+    GrapefruitError::TransactionError("transaction already finished".to_string())
+}
+
 impl<'a> Deref for TransactionManager<'a> {
-    type Target = PgPool;
+    type Target = PlatformPool;
 
     fn deref(&self) -> &Self::Target {
-        self.pool
+        self.grapefruit.pool()
     }
 }
 
-impl<'a> DerefMut for TransactionManager<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        self.pool
-    }
-}
\ No newline at end of file
+#[macro_export]
+macro_rules! exec_tx {
+    ($se:expr, $sql:expr, $params:expr, $fun:ident) => {{
+        let res = match $se {
+            #[cfg(feature = "mysql")]
+            PlatformTransaction::Mysql(tx) => {
+                let args =
+                    <Params as sqlx::IntoArguments<'_, sqlx::MySql>>::into_arguments($params);
+                let result = sqlx::query_with($sql, args).$fun(&mut **tx).await?;
+                result.into()
+            }
+            #[cfg(feature = "postgres")]
+            PlatformTransaction::Postgres(tx) => {
+                let args =
+                    <Params as sqlx::IntoArguments<'_, sqlx::Postgres>>::into_arguments($params);
+                let result = sqlx::query_with($sql, args).$fun(&mut **tx).await?;
+                result.into()
+            }
+            #[cfg(feature = "sqlite")]
+            PlatformTransaction::Sqlite(tx) => {
+                let args =
+                    <Params as sqlx::IntoArguments<'_, sqlx::Sqlite>>::into_arguments($params);
+                let result = sqlx::query_with($sql, args).$fun(&mut **tx).await?;
+                result.into()
+            }
+        };
+        res
+    }};
+}
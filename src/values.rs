@@ -9,7 +9,7 @@ use sqlx::types::{
 
 use crate::GrapefruitError;
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum ArrayType {
     Bool,
     Tinyint,
@@ -39,7 +39,7 @@ impl ArrayType {
     // pub fn to_value(&self)
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum Value {
     Bool(Option<bool>),
 
@@ -55,6 +55,7 @@ pub enum Value {
 
     Float(Option<f32>),
     Double(Option<f64>),
+    #[serde(with = "big_decimal_serde")]
     BigDecimal(Option<Box<BigDecimal>>),
 
     Char(Option<char>),
@@ -142,6 +143,81 @@ impl Value {
         }
     }
 
+    /// Next value of an optimistic lock version: integers are incremented,
+    /// timestamps are moved to the current time.
+    pub fn next_version(&self) -> Result<Value, GrapefruitError> {
+        let value = match self {
+            Value::Tinyint(Some(v)) => Value::Tinyint(Some(v.wrapping_add(1))),
+            Value::Smallint(Some(v)) => Value::Smallint(Some(v.wrapping_add(1))),
+            Value::Int(Some(v)) => Value::Int(Some(v.wrapping_add(1))),
+            Value::Bigint(Some(v)) => Value::Bigint(Some(v.wrapping_add(1))),
+            Value::TinyUnsigned(Some(v)) => Value::TinyUnsigned(Some(v.wrapping_add(1))),
+            Value::SmallUnsigned(Some(v)) => Value::SmallUnsigned(Some(v.wrapping_add(1))),
+            Value::Unsigned(Some(v)) => Value::Unsigned(Some(v.wrapping_add(1))),
+            Value::BigUnsigned(Some(v)) => Value::BigUnsigned(Some(v.wrapping_add(1))),
+            Value::ChronoDateTime(Some(_)) => Utc::now().naive_utc().into(),
+            Value::ChronoDateTimeUtc(Some(_)) => Utc::now().into(),
+            Value::ChronoDateTimeLocal(Some(_)) => Local::now().into(),
+            Value::ChronoDateTimeWithTimeZone(Some(v)) => {
+                Utc::now().with_timezone(v.offset()).into()
+            }
+            _ => {
+                return Err(GrapefruitError::ConvertError(
+                    format!("{:?} can not be used as version", self),
+                    "version".to_string(),
+                ))
+            }
+        };
+        Ok(value)
+    }
+
+    /// Convert into `T`, `None` for `NULL`. Numbers convert between numeric
+    /// types, e.g. a `NUMERIC` sum read back as `i64`, as long as the value
+    /// fits without losing precision.
+    pub fn convert<T>(self) -> Result<Option<T>, GrapefruitError>
+    where
+        T: ValueType,
+    {
+        if self.is_none() {
+            return Ok(None);
+        }
+        let text = match &self {
+            Value::Tinyint(Some(v)) => v.to_string(),
+            Value::Smallint(Some(v)) => v.to_string(),
+            Value::Int(Some(v)) => v.to_string(),
+            Value::Bigint(Some(v)) => v.to_string(),
+            Value::TinyUnsigned(Some(v)) => v.to_string(),
+            Value::SmallUnsigned(Some(v)) => v.to_string(),
+            Value::Unsigned(Some(v)) => v.to_string(),
+            Value::BigUnsigned(Some(v)) => v.to_string(),
+            Value::Float(Some(v)) => v.to_string(),
+            Value::Double(Some(v)) => v.to_string(),
+            Value::BigDecimal(Some(v)) => v.to_string(),
+            _ => return T::try_from(self).map(Some),
+        };
+        let error = || {
+            GrapefruitError::ConvertError(
+                format!("{:?} can not be converted", self),
+                T::type_name(),
+            )
+        };
+        let value: Value = match T::array_type() {
+            ArrayType::Tinyint => parse_integer::<i8>(&text).ok_or_else(error)?.into(),
+            ArrayType::Smallint => parse_integer::<i16>(&text).ok_or_else(error)?.into(),
+            ArrayType::Int => parse_integer::<i32>(&text).ok_or_else(error)?.into(),
+            ArrayType::Bigint => parse_integer::<i64>(&text).ok_or_else(error)?.into(),
+            ArrayType::TinyUnsigned => parse_integer::<u8>(&text).ok_or_else(error)?.into(),
+            ArrayType::SmallUnsigned => parse_integer::<u16>(&text).ok_or_else(error)?.into(),
+            ArrayType::Unsigned => parse_integer::<u32>(&text).ok_or_else(error)?.into(),
+            ArrayType::BigUnsigned => parse_integer::<u64>(&text).ok_or_else(error)?.into(),
+            ArrayType::Float => text.parse::<f32>().map_err(|_| error())?.into(),
+            ArrayType::Double => text.parse::<f64>().map_err(|_| error())?.into(),
+            ArrayType::BigDecimal => text.parse::<BigDecimal>().map_err(|_| error())?.into(),
+            _ => return Err(error()),
+        };
+        T::try_from(value).map(Some)
+    }
+
     pub fn get_array(&self) -> Vec<Value> {
         match self {
             Value::Array(_t, v) => match v {
@@ -159,6 +235,41 @@ impl Value {
     }
 }
 
+/// sqlx's `BigDecimal` has no serde support, so it goes by its string form
+mod big_decimal_serde {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use sqlx::types::BigDecimal;
+
+    pub fn serialize<S>(value: &Option<Box<BigDecimal>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        value.as_ref().map(|v| v.to_string()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Box<BigDecimal>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|text| text.parse().map(Box::new).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+/// `text` as an integer, accepting a decimal with no fractional part
+fn parse_integer<T>(text: &str) -> Option<T>
+where
+    T: std::str::FromStr,
+{
+    let integer = match text.split_once('.') {
+        Some((integer, fraction)) if fraction.chars().all(|c| c == '0') => integer,
+        Some(_) => return None,
+        None => text,
+    };
+    integer.parse().ok()
+}
+
 pub trait ValueType: Sized {
     fn try_from(v: Value) -> Result<Self, GrapefruitError>;
 
@@ -1,22 +1,37 @@
 use std::collections::HashMap;
 
-use crate::{add_condition, OrderByType, Platform};
-use crate::{Column, NotU8, Segment, SegmentType, Segments, Value, ValueType};
+use crate::{
+    add_condition, Aggregate, GrapefruitError, GrapefruitResult, LockMode, LockWait,
+    LogicDeleteScope, OrderByType, Platform, RowLock,
+};
+use crate::{Column, Entity, NotU8, Segment, SegmentType, Segments, SubQuery, Value, ValueType};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Wrapper {
     /// set 字段
     pub column_set: Vec<(String, Value)>,
+    /// set SQL片段
+    pub sql_set: Vec<String>,
+    /// set 为 NULL 的字段
+    pub null_set: Vec<String>,
     /// SQL查询字段
     pub sql_select: Option<String>,
+    /// 排除的查询字段
+    pub sql_exclude: Vec<String>,
     /// SQL起始语句
     pub sql_first: Option<String>,
     /// SQL结束语句
     pub sql_last: Option<String>,
+    /// 行锁
+    pub row_lock: Option<RowLock>,
+    /// 逻辑删除范围
+    pub logic_delete: LogicDeleteScope,
     /// expression
     pub expression: Segments,
     /// 层级
     pub bracket_num: i32,
+    /// first set column that could not be resolved, returned by `update_by_set`
+    pub error: Option<String>,
 }
 
 impl Wrapper {
@@ -27,11 +42,17 @@ impl Wrapper {
     pub fn with_bracket_num(bracket_num: i32) -> Self {
         Self {
             column_set: Vec::new(),
+            sql_set: Vec::new(),
+            null_set: Vec::new(),
             sql_select: None,
+            sql_exclude: Vec::new(),
             sql_first: None,
             sql_last: None,
+            row_lock: None,
+            logic_delete: LogicDeleteScope::NotDeleted,
             expression: Segments::with_bracket_num(bracket_num),
             bracket_num: bracket_num,
+            error: None,
         }
     }
 
@@ -68,13 +89,74 @@ impl Wrapper {
                 build_sql.push_str(s);
             }
         }
-        if build_sql.trim().is_empty() {
-            build_sql = " 1 = 1 ".to_string();
+        if self.expression.normal.is_empty() {
+            // keep the clause valid when only GROUP BY / ORDER BY are set
+            build_sql = format!(" 1 = 1 {}", build_sql.trim_start());
         }
         (build_sql, values)
     }
 }
 
+impl Wrapper {
+    /// Like `build`, with `column = val` added to the wrapper's conditions,
+    /// which are bracketed so their `or`s can not escape it
+    pub fn build_and(
+        &self,
+        platform: &Platform,
+        index: usize,
+        column: &str,
+        val: Value,
+    ) -> (String, Vec<Value>) {
+        self.and_all(vec![(column.to_string(), val)])
+            .build(platform, index)
+    }
+
+    /// Like `build_and`, with `(column = val OR column IS NULL)` added, for
+    /// a column that an outer join may leave without a row
+    pub fn build_and_or_null(
+        &self,
+        platform: &Platform,
+        index: usize,
+        column: &str,
+        val: Value,
+    ) -> (String, Vec<Value>) {
+        let mut condition = Self::with_bracket_num(-2);
+        condition.expression.normal = vec![
+            Segment::Eq(column.to_string(), val),
+            Segment::Or,
+            Segment::IsNull(column.to_string()),
+        ];
+        let mut wrapper = self.and_all(Vec::new());
+        wrapper
+            .expression
+            .normal
+            .push(Segment::Bracket(Box::new(condition)));
+        wrapper.build(platform, index)
+    }
+
+    /// Copy of the wrapper with a `column = val` condition per entry of
+    /// `conditions` added to its bracketed ones
+    pub(crate) fn and_all(&self, conditions: Vec<(String, Value)>) -> Wrapper {
+        let mut wrapper = self.clone();
+        let normal = std::mem::take(&mut wrapper.expression.normal);
+        // no nested wrapper uses a negative bracket number, so the param
+        // names of the added condition can not clash with the wrapper's own
+        wrapper.expression.bracket_num = -1;
+        if !normal.is_empty() {
+            let mut conditions = Self::with_bracket_num(self.bracket_num);
+            conditions.expression.normal = normal;
+            wrapper
+                .expression
+                .normal
+                .push(Segment::Bracket(Box::new(conditions)));
+        }
+        for (column, val) in conditions {
+            wrapper.expression.normal.push(Segment::Eq(column, val));
+        }
+        wrapper
+    }
+}
+
 impl Wrapper {
     pub fn do_it(mut self, condition: bool, segment_type: SegmentType, segment: Segment) -> Self {
         if condition {
@@ -83,16 +165,212 @@ impl Wrapper {
         self
     }
 
+    /// Set `column` to `val` in `update_by_set`
+    pub fn set<C, V>(mut self, column: C, val: V) -> Self
+    where
+        C: Column,
+        V: Into<Value>,
+    {
+        match column.alias() {
+            Ok(c) => self.column_set.push((c, val.into())),
+            Err(_) => self.fail("set column not set"),
+        }
+        self
+    }
+
+    /// Set `column` to `NULL` in `update_by_set`
+    pub fn set_null<C>(mut self, column: C) -> Self
+    where
+        C: Column,
+    {
+        match column.alias() {
+            Ok(c) => self.null_set.push(c),
+            Err(_) => self.fail("set column not set"),
+        }
+        self
+    }
+
+    fn fail(&mut self, error: &str) {
+        if self.error.is_none() {
+            self.error = Some(error.to_string());
+        }
+    }
+
+    /// Raw SET fragment for `update_by_set`, e.g. `count = count + 1`
+    pub fn set_sql(mut self, sql: &str) -> Self {
+        self.sql_set.push(sql.to_string());
+        self
+    }
+
+    /// Only query `columns`; the other fields come back as `Default`
+    pub fn select<C>(mut self, columns: &[C]) -> Self
+    where
+        C: Column,
+    {
+        let columns = columns
+            .iter()
+            .filter_map(|c| c.alias().ok())
+            .collect::<Vec<_>>();
+        self.sql_select = Some(columns.join(","));
+        self
+    }
+
+    /// Query every selectable column except `columns`
+    pub fn exclude<C>(mut self, columns: &[C]) -> Self
+    where
+        C: Column,
+    {
+        self.sql_exclude
+            .extend(columns.iter().filter_map(|c| c.alias().ok()));
+        self
+    }
+
     pub fn sql_first(mut self, sql: &str) -> Self {
         self.sql_first = Some(sql.to_string());
         self
     }
 
+    /// Raw SQL placed after the statement. Queries adding a LIMIT or a row
+    /// lock of their own, such as `select_one` or the pages, reject those in it
     pub fn sql_last(mut self, sql: &str) -> Self {
         self.sql_last = Some(sql.to_string());
         self
     }
 
+    /// Lock the selected rows against concurrent updates (`FOR UPDATE`)
+    pub fn for_update(self) -> Self {
+        self.lock_mode(LockMode::Update)
+    }
+
+    /// Lock the selected rows against concurrent updates while allowing
+    /// other readers to lock them too (`FOR SHARE`)
+    pub fn for_share(self) -> Self {
+        self.lock_mode(LockMode::Share)
+    }
+
+    /// Fail instead of waiting for rows locked elsewhere; implies
+    /// `for_update` unless another lock mode is set
+    pub fn nowait(self) -> Self {
+        self.lock_wait(LockWait::NoWait)
+    }
+
+    /// Leave out rows locked elsewhere instead of waiting for them;
+    /// implies `for_update` unless another lock mode is set
+    pub fn skip_locked(self) -> Self {
+        self.lock_wait(LockWait::SkipLocked)
+    }
+
+    fn lock_mode(mut self, mode: LockMode) -> Self {
+        let wait = self.row_lock.map_or(LockWait::Wait, |lock| lock.wait);
+        self.row_lock = Some(RowLock { mode, wait });
+        self
+    }
+
+    fn lock_wait(mut self, wait: LockWait) -> Self {
+        let mode = self.row_lock.map_or(LockMode::Update, |lock| lock.mode);
+        self.row_lock = Some(RowLock { mode, wait });
+        self
+    }
+
+    /// Include logically deleted rows
+    pub fn with_deleted(mut self) -> Self {
+        self.logic_delete = LogicDeleteScope::All;
+        self
+    }
+
+    /// Only logically deleted rows
+    pub fn only_deleted(mut self) -> Self {
+        self.logic_delete = LogicDeleteScope::Deleted;
+        self
+    }
+
+    /// `sql` with `sql_first` placed before the statement
+    pub fn with_sql_first(&self, sql: &str) -> String {
+        match &self.sql_first {
+            Some(first) => format!("{} {}", first, sql),
+            None => sql.to_string(),
+        }
+    }
+
+    /// `sql` with `sql_first` placed before the statement and `sql_last` after
+    /// it, on a line of its own so that a trailing comment ends there
+    pub fn with_sql_first_last(&self, sql: &str) -> String {
+        let sql = self.with_sql_first(sql);
+        match &self.sql_last {
+            Some(last) => format!("{} {}\n", sql.trim_end(), last),
+            None => sql,
+        }
+    }
+
+    /// Like `with_sql_first_last`, followed by the row lock clause of a SELECT
+    pub fn with_sql_first_last_lock(
+        &self,
+        sql: &str,
+        platform: &Platform,
+    ) -> GrapefruitResult<String> {
+        self.check_sql_last(false)?;
+        self.with_row_lock(&self.with_sql_first_last(sql), platform)
+    }
+
+    /// Like `with_sql_first_last_lock`, with `limit`, a LIMIT / OFFSET clause,
+    /// between `sql_last` and the row lock
+    pub fn with_sql_first_last_limit_lock(
+        &self,
+        sql: &str,
+        limit: &str,
+        platform: &Platform,
+    ) -> GrapefruitResult<String> {
+        self.check_sql_last(true)?;
+        let sql = format!(
+            "{} {}",
+            self.with_sql_first_last(sql).trim_end_matches(' '),
+            limit
+        );
+        self.with_row_lock(&sql, platform)
+    }
+
+    fn with_row_lock(&self, sql: &str, platform: &Platform) -> GrapefruitResult<String> {
+        match &self.row_lock {
+            Some(lock) => Ok(format!(
+                "{} {}",
+                sql.trim_end_matches(' '),
+                platform.row_lock(lock)?
+            )),
+            None => Ok(sql.to_string()),
+        }
+    }
+
+    /// Reject a `sql_last` holding a clause the query adds after it itself:
+    /// LIMIT / OFFSET when it sets its own `limit`, and a row lock when one
+    /// follows it
+    fn check_sql_last(&self, limit: bool) -> GrapefruitResult<()> {
+        let Some(last) = &self.sql_last else {
+            return Ok(());
+        };
+        let words = last
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .map(|word| word.to_uppercase())
+            .collect::<Vec<_>>();
+        let has = |word: &str| words.iter().any(|w| w == word);
+        if limit && (has("LIMIT") || has("OFFSET")) {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "sql_last `{}` can not hold LIMIT or OFFSET, the query adds its own",
+                last
+            )));
+        }
+        let lock = words
+            .windows(2)
+            .any(|w| w[0] == "FOR" && (w[1] == "UPDATE" || w[1] == "SHARE"))
+            || has("LOCK");
+        if lock && (limit || self.row_lock.is_some()) {
+            return Err(GrapefruitError::ObjectValidError(format!(
+                "sql_last `{}` can not hold a row lock, use for_update or for_share",
+                last
+            )));
+        }
+        Ok(())
+    }
+
     pub fn or(self) -> Self {
         self.do_it(true, SegmentType::Normal, Segment::Or)
     }
@@ -253,6 +531,79 @@ impl Wrapper {
         )
     }
 
+    /// `column IN (SELECT sub_column FROM <T> WHERE <sub>)`
+    pub fn in_sub<T>(self, column: impl Column, sub_column: impl Column, sub: Wrapper) -> Self
+    where
+        T: Entity,
+    {
+        let (Ok(c), Ok(sc)) = (column.alias(), sub_column.alias()) else {
+            return self;
+        };
+        self.do_it(
+            true,
+            SegmentType::Normal,
+            Segment::InSub(c, SubQuery::new::<T>(&sc, sub)),
+        )
+    }
+
+    /// `column NOT IN (SELECT sub_column FROM <T> WHERE <sub>)`
+    pub fn not_in_sub<T>(self, column: impl Column, sub_column: impl Column, sub: Wrapper) -> Self
+    where
+        T: Entity,
+    {
+        let (Ok(c), Ok(sc)) = (column.alias(), sub_column.alias()) else {
+            return self;
+        };
+        self.do_it(
+            true,
+            SegmentType::Normal,
+            Segment::NotInSub(c, SubQuery::new::<T>(&sc, sub)),
+        )
+    }
+
+    /// `EXISTS (SELECT 1 FROM <T> WHERE <sub>)`; without a `correlate` in
+    /// `sub` it holds for every row or for none
+    pub fn exists<T>(self, sub: Wrapper) -> Self
+    where
+        T: Entity,
+    {
+        self.do_it(
+            true,
+            SegmentType::Normal,
+            Segment::Exists(SubQuery::new::<T>("1", sub)),
+        )
+    }
+
+    /// `NOT EXISTS (SELECT 1 FROM <T> WHERE <sub>)`
+    pub fn not_exists<T>(self, sub: Wrapper) -> Self
+    where
+        T: Entity,
+    {
+        self.do_it(
+            true,
+            SegmentType::Normal,
+            Segment::NotExists(SubQuery::new::<T>("1", sub)),
+        )
+    }
+
+    /// In the wrapper of a subquery, `column = outer_column` with `column` on
+    /// the subquery's entity and `outer_column` on the one of the query it
+    /// sits in, so that `exists` and the others are checked per outer row.
+    /// Both are qualified with their table, which must therefore differ.
+    pub fn correlate(self, column: impl Column, outer_column: impl Column) -> Self {
+        let qualify = |column: &dyn Column| {
+            let alias = column.alias().ok()?;
+            Some(match column.table_name() {
+                Some(table) => format!("{}.{}", table, alias),
+                None => alias,
+            })
+        };
+        let (Some(c), Some(oc)) = (qualify(&column), qualify(&outer_column)) else {
+            return self;
+        };
+        self.do_it(true, SegmentType::Normal, Segment::Correlate(c, oc))
+    }
+
     pub fn between<C, V>(self, column: C, val1: V, val2: V) -> Self
     where
         C: Column,
@@ -340,6 +691,7 @@ impl Wrapper {
         self.do_it(condition, SegmentType::GroupBy, Segment::GroupBy(vec![c]))
     }
 
+    /// Raw HAVING SQL, added as is; use `having_with` for values
     pub fn having(self, sql_having: String) -> Self {
         self.having_condition(true, sql_having)
     }
@@ -348,10 +700,48 @@ impl Wrapper {
         self.do_it(
             condition,
             SegmentType::Having,
-            Segment::Having(vec![sql_having]),
+            Segment::Having(vec![(sql_having, vec![])]),
         )
     }
 
+    /// HAVING SQL with `{0}`, `{1}`, ... bound to `values`,
+    /// e.g. `having_with("sum(amount) > {0}", [100])`
+    pub fn having_with<V>(self, sql_having: &str, values: impl IntoIterator<Item = V>) -> Self
+    where
+        V: Into<Value>,
+    {
+        let values = values.into_iter().map(Into::into).collect();
+        self.do_it(
+            true,
+            SegmentType::Having,
+            Segment::Having(vec![(sql_having.to_string(), values)]),
+        )
+    }
+
+    pub fn having_eq<V: Into<Value>>(self, aggregate: Aggregate, val: V) -> Self {
+        self.having_with(&format!("{} = {{0}}", aggregate.sql()), [val])
+    }
+
+    pub fn having_ne<V: Into<Value>>(self, aggregate: Aggregate, val: V) -> Self {
+        self.having_with(&format!("{} <> {{0}}", aggregate.sql()), [val])
+    }
+
+    pub fn having_gt<V: Into<Value>>(self, aggregate: Aggregate, val: V) -> Self {
+        self.having_with(&format!("{} > {{0}}", aggregate.sql()), [val])
+    }
+
+    pub fn having_ge<V: Into<Value>>(self, aggregate: Aggregate, val: V) -> Self {
+        self.having_with(&format!("{} >= {{0}}", aggregate.sql()), [val])
+    }
+
+    pub fn having_lt<V: Into<Value>>(self, aggregate: Aggregate, val: V) -> Self {
+        self.having_with(&format!("{} < {{0}}", aggregate.sql()), [val])
+    }
+
+    pub fn having_le<V: Into<Value>>(self, aggregate: Aggregate, val: V) -> Self {
+        self.having_with(&format!("{} <= {{0}}", aggregate.sql()), [val])
+    }
+
     pub fn order_by<C>(self, column: C, is_asc: bool) -> Self
     where
         C: Column,
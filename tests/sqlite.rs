@@ -0,0 +1,1569 @@
+#![cfg(feature = "sqlite")]
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use futures::{StreamExt, TryStreamExt};
+use grapefruit::{
+    params, transactional, transactional_with, Aggregate, AliasColumn, BaseRepository, ColumnInfo,
+    Cursor, Grapefruit, GrapefruitError, GrapefruitOptions, GrapefruitRepository, IsolationLevel,
+    JoinType, JoinWrapper, PageRequest, Params, Propagation, RetryPolicy, TransactionManager,
+    TransactionOptions, Wrapper,
+};
+use grapefruit_macros::GrapefruitTable;
+
+#[derive(Debug, Default, Clone, GrapefruitTable)]
+#[table(name = "t_item")]
+pub struct Item {
+    #[id(name = "id", id_type = "generator")]
+    pub id: Option<i64>,
+    #[column(name = "name")]
+    pub name: String,
+}
+
+#[derive(Debug, Default, Clone, GrapefruitTable)]
+#[table(name = "t_doc")]
+pub struct Doc {
+    #[id(name = "id", id_type = "generator")]
+    pub id: Option<i64>,
+    #[column(name = "title")]
+    pub title: String,
+    #[column(name = "version", version = "true")]
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, GrapefruitTable)]
+#[table(name = "t_contact")]
+pub struct Contact {
+    #[id(name = "id", id_type = "generator")]
+    pub id: Option<i64>,
+    #[column(name = "name", update_strateg = "not_null")]
+    pub name: Option<String>,
+    #[column(
+        name = "addr",
+        insert_strateg = "not_null",
+        update_strateg = "not_null"
+    )]
+    pub addr: Option<String>,
+    #[column(name = "note", update_strateg = "never")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, GrapefruitTable)]
+#[table(name = "t_sale")]
+pub struct Sale {
+    #[id(name = "id", id_type = "generator")]
+    pub id: Option<i64>,
+    #[column(name = "region")]
+    pub region: String,
+    #[column(name = "amount")]
+    pub amount: i64,
+    #[column(name = "deleted", is_logic_delete = "true")]
+    pub deleted: i8,
+}
+
+/// projection of an item joined with its contact
+#[derive(Debug, Default, Clone, GrapefruitTable)]
+#[table(name = "t_item")]
+pub struct ItemContact {
+    #[id(name = "id", id_type = "generator")]
+    pub id: Option<i64>,
+    #[column(name = "name")]
+    pub name: String,
+    #[column(name = "contact_addr")]
+    pub addr: Option<String>,
+}
+
+pub struct ItemRepository {}
+
+impl BaseRepository<i64, Item> for ItemRepository {}
+
+fn item(name: &str) -> Item {
+    Item {
+        id: None,
+        name: name.into(),
+    }
+}
+
+async fn setup(db: &str) -> Grapefruit {
+    let url = format!("sqlite:file:{}?mode=memory&cache=shared", db);
+    let mut grapefruit = Grapefruit::new(&GrapefruitOptions::new(&url).with_min_connections(1));
+    grapefruit.init().await.unwrap();
+    grapefruit
+        .pool()
+        .execute(
+            "CREATE TABLE t_item (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+    grapefruit
+        .pool()
+        .execute(
+            "CREATE TABLE t_doc (id INTEGER PRIMARY KEY, title TEXT NOT NULL, version INTEGER)",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+    grapefruit
+        .pool()
+        .execute(
+            "CREATE TABLE t_contact (id INTEGER PRIMARY KEY, name TEXT UNIQUE, \
+             addr TEXT NOT NULL DEFAULT 'unknown', note TEXT)",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+    grapefruit
+        .pool()
+        .execute(
+            "CREATE TABLE t_sale (id INTEGER PRIMARY KEY, region TEXT NOT NULL, \
+             amount INTEGER NOT NULL, deleted INTEGER NOT NULL)",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+    grapefruit
+}
+
+#[tokio::test]
+async fn test_transaction_commit_and_rollback() {
+    let grapefruit = setup("tx_commit_and_rollback").await;
+    let manager = TransactionManager::new(&grapefruit);
+
+    manager
+        .with_transaction(|tx| async move { tx.insert(&item("a")).await })
+        .await
+        .unwrap();
+    let res: Result<u64, _> = manager
+        .with_transaction(|tx| async move {
+            tx.insert(&item("b")).await?;
+            Err(GrapefruitError::Unknown)
+        })
+        .await;
+
+    assert!(res.is_err());
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_savepoint_rollback_keeps_outer_work() {
+    let grapefruit = setup("tx_savepoint_rollback").await;
+
+    let tx = grapefruit.begin().await.unwrap();
+    tx.insert(&item("outer")).await.unwrap();
+
+    let inner = tx.begin().await.unwrap();
+    assert_eq!(inner.depth(), 1);
+    inner.insert(&item("inner")).await.unwrap();
+    assert_eq!(tx.count_all::<Item>().await.unwrap(), 2);
+    inner.rollback().await.unwrap();
+
+    assert_eq!(tx.count_all::<Item>().await.unwrap(), 1);
+    tx.commit().await.unwrap();
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_savepoint_release_is_undone_by_outer_rollback() {
+    let grapefruit = setup("tx_savepoint_release").await;
+
+    let tx = grapefruit.begin().await.unwrap();
+    let inner = tx.begin().await.unwrap();
+    inner.insert(&item("inner")).await.unwrap();
+    inner.commit().await.unwrap();
+    assert_eq!(tx.count_all::<Item>().await.unwrap(), 1);
+    tx.rollback().await.unwrap();
+
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_nested_with_transaction() {
+    let grapefruit = setup("tx_nested_with_transaction").await;
+
+    TransactionManager::new(&grapefruit)
+        .with_transaction(|tx| async move {
+            tx.insert(&item("outer")).await?;
+            let res: Result<u64, _> = TransactionManager::new(tx.grapefruit())
+                .with_transaction(|inner| async move {
+                    inner.insert(&item("inner")).await?;
+                    Err(GrapefruitError::Unknown)
+                })
+                .await;
+            assert!(res.is_err());
+            TransactionManager::new(tx.grapefruit())
+                .with_transaction(|inner| async move { inner.insert(&item("inner")).await })
+                .await
+        })
+        .await
+        .unwrap();
+
+    let names = grapefruit
+        .select_all::<Item>()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|item| item.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["outer", "inner"]);
+}
+
+#[tokio::test]
+async fn test_read_only_transaction() {
+    let grapefruit = setup("tx_read_only").await;
+    let manager = TransactionManager::new(&grapefruit);
+    let options = TransactionOptions::new().with_read_only(true);
+
+    let res = manager
+        .with_transaction_options(&options, |tx| async move { tx.insert(&item("a")).await })
+        .await;
+    assert!(matches!(res, Err(GrapefruitError::SqlError(_))));
+
+    manager
+        .with_transaction(|tx| async move { tx.insert(&item("a")).await })
+        .await
+        .unwrap();
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 1);
+
+    let options = TransactionOptions::new().with_isolation_level(IsolationLevel::Serializable);
+    let res = manager
+        .with_transaction_options(&options, |tx| async move { tx.insert(&item("b")).await })
+        .await;
+    assert!(matches!(res, Err(GrapefruitError::PlatformError(_))));
+}
+
+#[tokio::test]
+async fn test_dropped_read_only_transaction() {
+    // a single connection, so the insert reuses the read-only one
+    let url = "sqlite:file:tx_dropped_read_only?mode=memory&cache=shared";
+    let options = GrapefruitOptions::new(url)
+        .with_min_connections(1)
+        .with_max_connections(1);
+    let mut grapefruit = Grapefruit::new(&options);
+    grapefruit.init().await.unwrap();
+    grapefruit
+        .pool()
+        .execute(
+            "CREATE TABLE t_item (id INTEGER PRIMARY KEY, name TEXT NOT NULL)",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+
+    let options = TransactionOptions::new().with_read_only(true);
+    let tx = grapefruit.begin_with(&options).await.unwrap();
+    assert_eq!(tx.count_all::<Item>().await.unwrap(), 0);
+    drop(tx);
+
+    grapefruit.insert(&item("a")).await.unwrap();
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 1);
+}
+
+/// Database error carrying a serialization failure code
+#[derive(Debug)]
+struct SerializationFailure;
+
+impl std::fmt::Display for SerializationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("could not serialize access")
+    }
+}
+
+impl std::error::Error for SerializationFailure {}
+
+impl sqlx::error::DatabaseError for SerializationFailure {
+    fn message(&self) -> &str {
+        "could not serialize access"
+    }
+
+    fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+        Some("40001".into())
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> sqlx::error::ErrorKind {
+        sqlx::error::ErrorKind::Other
+    }
+}
+
+#[tokio::test]
+async fn test_retry_on_retryable_error() {
+    let grapefruit = setup("tx_retry_retryable").await;
+    let attempts = AtomicU32::new(0);
+    let options = TransactionOptions::new()
+        .with_retry(RetryPolicy::new(3).with_backoff(std::time::Duration::from_millis(1)));
+
+    let res = TransactionManager::new(&grapefruit)
+        .with_transaction_options(&options, |tx| {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                tx.insert(&item("a")).await?;
+                if attempt < 2 {
+                    let e = sqlx::Error::Database(Box::new(SerializationFailure));
+                    return Err(GrapefruitError::SqlError(e));
+                }
+                Ok(attempt)
+            }
+        })
+        .await;
+
+    assert_eq!(res.unwrap(), 2);
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    // the failed attempts were rolled back
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_retry_skips_non_retryable_errors() {
+    let grapefruit = setup("tx_retry").await;
+    let attempts = AtomicU32::new(0);
+    let options = TransactionOptions::new().with_retry(RetryPolicy::new(3));
+
+    let res: Result<(), _> = TransactionManager::new(&grapefruit)
+        .with_transaction_options(&options, |_| async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(GrapefruitError::Unknown)
+        })
+        .await;
+
+    assert!(res.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_transactional_base_repository() {
+    let grapefruit = setup("tx_transactional").await;
+    grapefruit::GRAPEFRUIT.set(grapefruit.clone()).ok();
+    let repository = ItemRepository {};
+
+    let res: Result<u64, _> = transactional(async {
+        repository.insert(&item("a")).await?;
+        assert_eq!(repository.count_all().await?, 1);
+        Err(GrapefruitError::Unknown)
+    })
+    .await;
+    assert!(res.is_err());
+    assert_eq!(repository.count_all().await.unwrap(), 0);
+
+    transactional(async {
+        repository.insert(&item("outer")).await?;
+        let res: Result<u64, _> = transactional_with(Propagation::Nested, async {
+            repository.insert(&item("nested")).await?;
+            Err(GrapefruitError::Unknown)
+        })
+        .await;
+        assert!(res.is_err());
+        transactional(async { repository.insert(&item("joined")).await }).await
+    })
+    .await
+    .unwrap();
+
+    let names = repository
+        .select_all()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|item| item.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["outer", "joined"]);
+}
+
+#[tokio::test]
+async fn test_optimistic_lock() {
+    let grapefruit = setup("optimistic_lock").await;
+    let doc = Doc {
+        id: None,
+        title: "draft".into(),
+        version: Some(1),
+    };
+    grapefruit.insert(&doc).await.unwrap();
+    let mut first = grapefruit.select_all::<Doc>().await.unwrap().remove(0);
+    let mut second = first.clone();
+
+    first.title = "first".into();
+    assert_eq!(grapefruit.update_by_id(&first).await.unwrap(), 1);
+    second.title = "second".into();
+    let res = grapefruit.update_by_id(&second).await;
+    assert!(matches!(
+        res,
+        Err(GrapefruitError::OptimisticLockConflict(_))
+    ));
+
+    let stored = grapefruit.select_all::<Doc>().await.unwrap().remove(0);
+    assert_eq!(stored.title, "first");
+    assert_eq!(stored.version, Some(2));
+
+    let wrapper = Wrapper::new().eq(DocDef::Title, "first");
+    let res = grapefruit.update_by_wrapper(&second, wrapper).await;
+    assert!(matches!(
+        res,
+        Err(GrapefruitError::OptimisticLockConflict(_))
+    ));
+    // matching no row at all is not a conflict
+    let wrapper = Wrapper::new().eq(DocDef::Title, "missing");
+    let rows = grapefruit
+        .update_by_wrapper(&second, wrapper)
+        .await
+        .unwrap();
+    assert_eq!(rows, 0);
+
+    second.version = None;
+    assert_eq!(grapefruit.update_by_id(&second).await.unwrap(), 1);
+    let stored = grapefruit.select_all::<Doc>().await.unwrap().remove(0);
+    assert_eq!(stored.title, "second");
+    assert_eq!(stored.version, Some(2));
+}
+
+#[tokio::test]
+async fn test_column_strategies() {
+    let grapefruit = setup("column_strategies").await;
+    let contact = |name: &str, addr: Option<&str>| Contact {
+        id: None,
+        name: Some(name.into()),
+        addr: addr.map(Into::into),
+        note: Some("created".into()),
+    };
+
+    let rows = grapefruit
+        .insert_batch(&[
+            &contact("a", Some("home")),
+            &contact("b", None),
+            &contact("c", Some("work")),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(rows, 3);
+
+    let mut contacts = grapefruit.select_all::<Contact>().await.unwrap();
+    contacts.sort_by(|a, b| a.name.cmp(&b.name));
+    let addrs = contacts
+        .iter()
+        .map(|c| c.addr.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(addrs, vec!["home", "unknown", "work"]);
+
+    let mut partial = contacts.remove(0);
+    partial.name = None;
+    partial.addr = Some("office".into());
+    partial.note = Some("changed".into());
+    assert_eq!(grapefruit.update_by_id(&partial).await.unwrap(), 1);
+
+    let stored: Contact = grapefruit
+        .select_by_id(partial.id.unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.name.as_deref(), Some("a"));
+    assert_eq!(stored.addr.as_deref(), Some("office"));
+    assert_eq!(stored.note.as_deref(), Some("created"));
+}
+
+#[tokio::test]
+async fn test_upsert() {
+    let grapefruit = setup("upsert").await;
+    let mut first = item("a");
+    first.id = Some(1);
+    assert_eq!(grapefruit.upsert(&first).await.unwrap(), 1);
+    first.name = "b".into();
+    grapefruit.upsert(&first).await.unwrap();
+    let items = grapefruit.select_all::<Item>().await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "b");
+
+    let contact = |name: &str, addr: Option<&str>, note: &str| Contact {
+        id: None,
+        name: Some(name.into()),
+        addr: addr.map(Into::into),
+        note: Some(note.into()),
+    };
+    grapefruit
+        .insert(&contact("a", Some("home"), "created"))
+        .await
+        .unwrap();
+    grapefruit
+        .upsert_batch_on(
+            &[
+                &contact("a", None, "synced"),
+                &contact("z", Some("work"), "synced"),
+            ],
+            &["name"],
+        )
+        .await
+        .unwrap();
+    let mut contacts = grapefruit.select_all::<Contact>().await.unwrap();
+    contacts.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(contacts.len(), 2);
+    assert_eq!(contacts[0].addr.as_deref(), Some("home"));
+    assert_eq!(contacts[0].note.as_deref(), Some("created"));
+    assert_eq!(contacts[1].addr.as_deref(), Some("work"));
+}
+
+#[tokio::test]
+async fn test_upsert_optimistic_lock() {
+    let grapefruit = setup("upsert_optimistic_lock").await;
+    let mut doc = Doc {
+        id: Some(1),
+        title: "draft".into(),
+        version: Some(1),
+    };
+    grapefruit.upsert(&doc).await.unwrap();
+    doc.title = "first".into();
+    assert_eq!(grapefruit.upsert(&doc).await.unwrap(), 1);
+
+    doc.title = "stale".into();
+    let res = grapefruit.upsert_batch(&[&doc]).await;
+    assert!(matches!(
+        res,
+        Err(GrapefruitError::OptimisticLockConflict(_))
+    ));
+
+    let stored = grapefruit.select_all::<Doc>().await.unwrap().remove(0);
+    assert_eq!(stored.title, "first");
+    assert_eq!(stored.version, Some(2));
+
+    // an upsert without a version is not guarded but still bumps it
+    doc.title = "unversioned".into();
+    doc.version = None;
+    assert_eq!(grapefruit.upsert(&doc).await.unwrap(), 1);
+    let stored = grapefruit.select_all::<Doc>().await.unwrap().remove(0);
+    assert_eq!(stored.title, "unversioned");
+    assert_eq!(stored.version, Some(3));
+}
+
+#[tokio::test]
+async fn test_insert_returning() {
+    let grapefruit = setup("insert_returning").await;
+    let contact = Contact {
+        id: None,
+        name: Some("a".into()),
+        addr: None,
+        note: None,
+    };
+
+    let stored = grapefruit.insert_returning(&contact).await.unwrap();
+    assert!(stored.id.is_some());
+    assert_eq!(stored.name.as_deref(), Some("a"));
+    assert_eq!(stored.addr.as_deref(), Some("unknown"));
+
+    let selected: Contact = grapefruit
+        .select_by_id(stored.id.unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(selected.id, stored.id);
+}
+
+#[tokio::test]
+async fn test_insert_batch_chunked() {
+    let grapefruit = setup("insert_batch_chunked").await;
+    // two binds per row, beyond SQLite's 32766 limit for one statement
+    let items = (0..20_000)
+        .map(|i| item(&format!("item-{}", i)))
+        .collect::<Vec<_>>();
+    let refs = items.iter().collect::<Vec<_>>();
+
+    assert_eq!(grapefruit.insert_batch(&refs).await.unwrap(), 20_000);
+    assert_eq!(grapefruit.count_all::<Item>().await.unwrap(), 20_000);
+}
+
+#[tokio::test]
+async fn test_select_projection() {
+    let grapefruit = setup("select_projection").await;
+    for name in ["a", "b"] {
+        let contact = Contact {
+            id: None,
+            name: Some(name.into()),
+            addr: Some("home".into()),
+            note: Some("long text".into()),
+        };
+        grapefruit.insert(&contact).await.unwrap();
+    }
+
+    let wrapper = Wrapper::new().select(&[ContactDef::Id, ContactDef::Name]);
+    let contacts: Vec<Contact> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(contacts.len(), 2);
+    assert!(contacts.iter().all(|c| c.id.is_some() && c.name.is_some()));
+    assert!(contacts
+        .iter()
+        .all(|c| c.addr.is_none() && c.note.is_none()));
+
+    let wrapper = Wrapper::new()
+        .eq(ContactDef::Name, "b")
+        .exclude(&[ContactDef::Note]);
+    let contact: Contact = grapefruit.select_one(wrapper).await.unwrap().unwrap();
+    assert_eq!(contact.name.as_deref(), Some("b"));
+    assert_eq!(contact.addr.as_deref(), Some("home"));
+    assert_eq!(contact.note, None);
+
+    let wrapper = Wrapper::new().select(&[ContactDef::Name]);
+    let page = grapefruit
+        .page_by_wrapper::<Contact>(1, 10, wrapper)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 2);
+    assert!(page.records.iter().all(|c| c.id.is_none()));
+
+    let wrapper = Wrapper::new().eq(ContactDef::Name, "z");
+    assert!(grapefruit
+        .select_one::<Contact>(wrapper)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_update_by_set() {
+    let grapefruit = setup("update_by_set").await;
+    let doc = Doc {
+        id: None,
+        title: "draft".into(),
+        version: Some(1),
+    };
+    grapefruit.insert(&doc).await.unwrap();
+    let contact = Contact {
+        id: None,
+        name: Some("a".into()),
+        addr: Some("home".into()),
+        note: Some("created".into()),
+    };
+    grapefruit.insert(&contact).await.unwrap();
+
+    let wrapper = Wrapper::new()
+        .set(DocDef::Title, "published")
+        .eq(DocDef::Title, "draft");
+    assert_eq!(grapefruit.update_by_set::<Doc>(wrapper).await.unwrap(), 1);
+    let stored = grapefruit.select_all::<Doc>().await.unwrap().remove(0);
+    assert_eq!(stored.title, "published");
+    assert_eq!(stored.version, Some(2));
+
+    let wrapper = Wrapper::new()
+        .set_sql("name = name || '!'")
+        .set_null(ContactDef::Note)
+        .eq(ContactDef::Name, "a");
+    assert_eq!(
+        grapefruit.update_by_set::<Contact>(wrapper).await.unwrap(),
+        1
+    );
+    let stored = grapefruit.select_all::<Contact>().await.unwrap().remove(0);
+    assert_eq!(stored.name.as_deref(), Some("a!"));
+    assert_eq!(stored.addr.as_deref(), Some("home"));
+    assert_eq!(stored.note, None);
+
+    let res = grapefruit.update_by_set::<Contact>(Wrapper::new()).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+    // a column that can not be resolved fails the update instead of being skipped
+    let wrapper = Wrapper::new()
+        .set(ContactDef::Addr, "work")
+        .set_null(None::<ColumnInfo>);
+    let res = grapefruit.update_by_set::<Contact>(wrapper).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+    let stored = grapefruit.select_all::<Contact>().await.unwrap().remove(0);
+    assert_eq!(stored.addr.as_deref(), Some("home"));
+}
+
+#[tokio::test]
+async fn test_sql_first_and_last() {
+    let grapefruit = setup("sql_first_and_last").await;
+    for name in ["a", "b", "c"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+
+    let wrapper = Wrapper::new()
+        .sql_first("/* first */")
+        .sql_last("ORDER BY name DESC LIMIT 1");
+    let items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "c");
+
+    let wrapper = Wrapper::new()
+        .ne(ItemDef::Name, "a")
+        .sql_first("/* first */")
+        .sql_last("ORDER BY name DESC");
+    let page = grapefruit
+        .page_by_wrapper::<Item>(1, 1, wrapper)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 2);
+    assert_eq!(page.records.len(), 1);
+    assert_eq!(page.records[0].name, "c");
+
+    let wrapper = Wrapper::new().sql_last("ORDER BY name DESC");
+    let item: Option<Item> = grapefruit.select_one(wrapper).await.unwrap();
+    assert_eq!(item.unwrap().name, "c");
+
+    let wrapper = Wrapper::new().sql_first("/* first */").sql_last("-- last");
+    assert_eq!(
+        grapefruit.count_by_wrapper::<Item>(wrapper).await.unwrap(),
+        3
+    );
+
+    // a trailing comment does not swallow the generated LIMIT
+    let wrapper = Wrapper::new().sql_last("ORDER BY name -- last");
+    let page = grapefruit
+        .page_by_wrapper::<Item>(1, 2, wrapper)
+        .await
+        .unwrap();
+    assert_eq!(page.records.len(), 2);
+    assert_eq!(page.records[1].name, "b");
+
+    // queries that add their own LIMIT reject one in sql_last
+    let wrapper = Wrapper::new().sql_last("LIMIT 1");
+    let res = grapefruit.select_one::<Item>(wrapper).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+    let wrapper = Wrapper::new().sql_last("limit 1 offset 1");
+    let res = grapefruit.page_by_wrapper::<Item>(1, 2, wrapper).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+    let wrapper = Wrapper::new().sql_last("FOR UPDATE");
+    let res = grapefruit.select_one::<Item>(wrapper).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+}
+
+#[tokio::test]
+async fn test_row_lock_rejected() {
+    let grapefruit = setup("row_lock_rejected").await;
+    grapefruit.insert(&item("a")).await.unwrap();
+
+    let tx = grapefruit.begin().await.unwrap();
+    let wrapper = Wrapper::new()
+        .eq(ItemDef::Name, "a")
+        .for_update()
+        .skip_locked();
+    let res = tx.select_one::<Item>(wrapper).await;
+    assert!(matches!(res, Err(GrapefruitError::PlatformError(_))));
+    tx.rollback().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_subquery_conditions() {
+    let grapefruit = setup("subquery_conditions").await;
+    for name in ["a", "b", "c"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+    for (name, addr) in [("a", "x"), ("b", "y"), ("c", "x")] {
+        let contact = Contact {
+            id: None,
+            name: Some(name.into()),
+            addr: Some(addr.into()),
+            note: None,
+        };
+        grapefruit.insert(&contact).await.unwrap();
+    }
+
+    let wrapper = Wrapper::new()
+        .ne(ItemDef::Name, "c")
+        .in_sub::<Contact>(
+            ItemDef::Name,
+            ContactDef::Name,
+            Wrapper::new()
+                .eq(ContactDef::Addr, "x")
+                .ne(ContactDef::Name, "b"),
+        )
+        .ne(ItemDef::Name, "b");
+    let items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "a");
+
+    let wrapper = Wrapper::new().not_in_sub::<Contact>(
+        ItemDef::Name,
+        ContactDef::Name,
+        Wrapper::new().eq(ContactDef::Addr, "x"),
+    );
+    let items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "b");
+
+    let wrapper = Wrapper::new()
+        .exists::<Contact>(Wrapper::new().eq(ContactDef::Addr, "y"))
+        .eq(ItemDef::Name, "a");
+    assert_eq!(
+        grapefruit.count_by_wrapper::<Item>(wrapper).await.unwrap(),
+        1
+    );
+
+    let wrapper = Wrapper::new().not_exists::<Contact>(Wrapper::new().eq(ContactDef::Addr, "z"));
+    assert_eq!(
+        grapefruit.count_by_wrapper::<Item>(wrapper).await.unwrap(),
+        3
+    );
+    let wrapper = Wrapper::new().not_exists::<Contact>(Wrapper::new());
+    assert_eq!(
+        grapefruit.count_by_wrapper::<Item>(wrapper).await.unwrap(),
+        0
+    );
+
+    // correlated, the subquery is checked against each item
+    let wrapper = Wrapper::new().exists::<Contact>(
+        Wrapper::new()
+            .correlate(ContactDef::Name, ItemDef::Name)
+            .eq(ContactDef::Addr, "x"),
+    );
+    let mut items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].name, "a");
+    assert_eq!(items[1].name, "c");
+    let wrapper = Wrapper::new().not_exists::<Contact>(
+        Wrapper::new()
+            .eq(ContactDef::Addr, "x")
+            .correlate(ContactDef::Name, ItemDef::Name),
+    );
+    let items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "b");
+}
+
+#[tokio::test]
+async fn test_grouped_subquery_with_logic_delete() {
+    let grapefruit = setup("grouped_subquery_with_logic_delete").await;
+    for name in ["a", "b", "c"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+    for (region, amount) in [("a", 1), ("a", 2), ("b", 5), ("c", 4)] {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount,
+            deleted: 0,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+    let deleted = Wrapper::new().eq(SaleDef::Region, "c");
+    assert!(grapefruit.delete_by_wrapper::<Sale>(deleted).await.unwrap());
+
+    // the sub-wrapper's `or` stays within the rows not deleted
+    let wrapper = Wrapper::new().in_sub::<Sale>(
+        ItemDef::Name,
+        SaleDef::Region,
+        Wrapper::new()
+            .eq(SaleDef::Region, "a")
+            .or()
+            .eq(SaleDef::Region, "c")
+            .group_by(SaleDef::Region)
+            .having_with("sum(amount) > {0}", [2]),
+    );
+    let items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "a");
+
+    let wrapper = Wrapper::new()
+        .eq(ItemDef::Name, "b")
+        .exists::<Sale>(Wrapper::new().group_by(SaleDef::Region))
+        .not_in_sub::<Sale>(
+            ItemDef::Name,
+            SaleDef::Region,
+            Wrapper::new().only_deleted().order_by_asc(SaleDef::Region),
+        );
+    let items: Vec<Item> = grapefruit.select_by_wrapper(wrapper).await.unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].name, "b");
+}
+
+#[tokio::test]
+async fn test_join() {
+    let grapefruit = setup("join").await;
+    for name in ["a", "b", "c"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+    for (name, addr) in [("a", "x"), ("b", "y")] {
+        let contact = Contact {
+            id: None,
+            name: Some(name.into()),
+            addr: Some(addr.into()),
+            note: None,
+        };
+        grapefruit.insert(&contact).await.unwrap();
+    }
+
+    let join = JoinWrapper::from::<Item>()
+        .left_join::<Contact>(ItemDef::Name, ContactDef::Name)
+        .order_by_asc(ItemDef::Name);
+    let rows: Vec<(Item, Option<Contact>)> = grapefruit.select_join(join).await.unwrap();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0].0.name, "a");
+    assert_eq!(rows[0].1.as_ref().unwrap().addr.as_deref(), Some("x"));
+    assert_eq!(rows[1].1.as_ref().unwrap().name.as_deref(), Some("b"));
+    assert_eq!(rows[2].0.name, "c");
+    assert!(rows[2].1.is_none());
+
+    let join = JoinWrapper::from::<Item>()
+        .inner_join::<Contact>(ItemDef::Name, ContactDef::Name)
+        .ne(ItemDef::Name, "z")
+        .or_fn(|j| j.eq(ContactDef::Addr, "y").eq(ItemDef::Name, "b"));
+    let rows: Vec<(Item, Contact)> = grapefruit.select_join(join).await.unwrap();
+    assert_eq!(rows.len(), 2);
+
+    let join = JoinWrapper::from::<Item>()
+        .left_join::<Contact>(ItemDef::Name, ContactDef::Name)
+        .eq(ContactDef::Addr, "y");
+    let res = grapefruit.select_join::<(Item, Contact, Item)>(join).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+
+    let join = JoinWrapper::from::<Item>()
+        .left_join::<Contact>(ItemDef::Name, ContactDef::Name)
+        .select(ItemDef::Id)
+        .select(ItemDef::Name)
+        .select_as(ContactDef::Addr, "contact_addr")
+        .is_not_null(ContactDef::Id)
+        .order_by_desc(ItemDef::Name);
+    let rows: Vec<ItemContact> = grapefruit.select_join_as(join).await.unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].name, "b");
+    assert_eq!(rows[0].addr.as_deref(), Some("y"));
+}
+
+#[tokio::test]
+async fn test_right_join_with_logic_delete() {
+    let grapefruit = setup("right_join_with_logic_delete").await;
+    for name in ["a", "b", "c"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+    for region in ["a", "b"] {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount: 1,
+            deleted: 0,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+    let deleted = Wrapper::new().eq(SaleDef::Region, "b");
+    assert!(grapefruit.delete_by_wrapper::<Sale>(deleted).await.unwrap());
+
+    // items without a sale keep their row, the deleted sale's does not
+    let join = JoinWrapper::from::<Sale>()
+        .right_join::<Item>(SaleDef::Region, ItemDef::Name)
+        .order_by_asc(ItemDef::Name);
+    let rows: Vec<(Option<Sale>, Item)> = grapefruit.select_join(join).await.unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].0.as_ref().unwrap().region, "a");
+    assert_eq!(rows[1].1.name, "c");
+    assert!(rows[1].0.is_none());
+}
+
+#[tokio::test]
+async fn test_self_join() {
+    let grapefruit = setup("self_join").await;
+    for name in ["a", "b", "c"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+
+    let join = JoinWrapper::from::<Item>()
+        .inner_join::<Item>(ItemDef::Name, ItemDef::Name)
+        .join_as::<Item>(
+            JoinType::Inner,
+            "t2",
+            AliasColumn::new("t1", ItemDef::Name),
+            ItemDef::Name,
+        )
+        .eq(AliasColumn::new("t2", ItemDef::Name), "b");
+    let rows: Vec<(Item, Item, Item)> = grapefruit.select_join(join).await.unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].0.name, "b");
+    assert_eq!(rows[0].2.name, "b");
+
+    let join = JoinWrapper::from::<Item>()
+        .left_join::<Item>(ItemDef::Name, ItemDef::Name)
+        .select(ItemDef::Id)
+        .select(ItemDef::Name)
+        .select_as(AliasColumn::new("t1", ItemDef::Name), "contact_addr")
+        .order_by_desc(AliasColumn::new("t1", ItemDef::Name));
+    let rows: Vec<ItemContact> = grapefruit.select_join_as(join).await.unwrap();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0].addr.as_deref(), Some("c"));
+
+    // columns of tables outside the join are reported, not panicked on
+    let join = JoinWrapper::from::<Item>().eq(ContactDef::Addr, "x");
+    let res = grapefruit.select_join_as::<Item>(join).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+    let join = JoinWrapper::from::<Item>()
+        .inner_join::<Item>(ItemDef::Name, ItemDef::Name)
+        .or_fn(|j| j.eq(AliasColumn::new("t2", ItemDef::Name), "a"));
+    let res = grapefruit.select_join::<(Item, Item)>(join).await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+}
+
+#[tokio::test]
+async fn test_aggregates() {
+    let grapefruit = setup("aggregates").await;
+    for (region, amount, deleted) in [("n", 10, 0), ("n", 20, 0), ("s", 5, 0), ("s", 100, 1)] {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount,
+            deleted,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+
+    let sum: Option<i64> = grapefruit
+        .select_aggregate::<Sale, _>(Aggregate::sum(SaleDef::Amount), Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(sum, Some(35));
+    let avg: Option<f64> = grapefruit
+        .select_aggregate::<Sale, _>(
+            Aggregate::avg(SaleDef::Amount),
+            Wrapper::new().eq(SaleDef::Region, "n"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(avg, Some(15.0));
+    let max: Option<i32> = grapefruit
+        .select_aggregate::<Sale, _>(Aggregate::max(SaleDef::Amount), Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(max, Some(20));
+    let regions: Option<i64> = grapefruit
+        .select_aggregate::<Sale, _>(Aggregate::count_distinct(SaleDef::Region), Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(regions, Some(2));
+    let min: Option<i64> = grapefruit
+        .select_aggregate::<Sale, _>(
+            Aggregate::min(SaleDef::Amount),
+            Wrapper::new().eq(SaleDef::Region, "w"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(min, None);
+
+    for (name, note) in [("a", Some("x")), ("b", None), ("c", Some("x"))] {
+        let contact = Contact {
+            id: None,
+            name: Some(name.into()),
+            addr: Some("a".into()),
+            note: note.map(Into::into),
+        };
+        grapefruit.insert(&contact).await.unwrap();
+    }
+    let notes: Option<i64> = grapefruit
+        .select_aggregate::<Contact, _>(Aggregate::count_column(ContactDef::Note), Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(notes, Some(2));
+    let notes: Option<i64> = grapefruit
+        .select_aggregate::<Contact, _>(Aggregate::count_distinct(ContactDef::Note), Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(notes, Some(1));
+
+    let wrapper = Wrapper::new()
+        .eq(SaleDef::Region, "n")
+        .or()
+        .eq(SaleDef::Region, "s")
+        .group_by(SaleDef::Region)
+        .order_by_asc(SaleDef::Region);
+    let groups = grapefruit
+        .select_grouped::<Sale>(
+            &[Aggregate::count(), Aggregate::sum(SaleDef::Amount)],
+            wrapper,
+        )
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 2);
+    let (group, aggregates) = &groups[1];
+    assert_eq!(group["region"], "s".into());
+    assert_eq!(aggregates["count"], 1i64.into());
+    assert_eq!(aggregates["sum_amount"], 5i64.into());
+
+    let res = grapefruit
+        .select_grouped::<Sale>(&[Aggregate::count()], Wrapper::new())
+        .await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+}
+
+#[tokio::test]
+async fn test_grouped_without_logic_delete() {
+    let grapefruit = setup("grouped_without_logic_delete").await;
+    for name in ["a", "a", "b"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+
+    let wrapper = Wrapper::new()
+        .group_by(ItemDef::Name)
+        .order_by_asc(ItemDef::Name);
+    let groups = grapefruit
+        .select_grouped::<Item>(&[Aggregate::count()], wrapper)
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 2);
+    let (group, aggregates) = &groups[0];
+    assert_eq!(group["name"], "a".into());
+    assert_eq!(aggregates["count"], 2i64.into());
+}
+
+#[tokio::test]
+async fn test_having_params() {
+    let grapefruit = setup("having_params").await;
+    for (region, amount) in [("n", 10), ("n", 20), ("s", 5), ("e", 7), ("e", 8)] {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount,
+            deleted: 0,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+
+    let wrapper = Wrapper::new()
+        .ne(SaleDef::Region, "x")
+        .group_by(SaleDef::Region)
+        .having_gt(Aggregate::count(), 1)
+        .having_with("sum(amount) between {0} and {1}", [20, 40])
+        .order_by_asc(SaleDef::Region);
+    let groups = grapefruit
+        .select_grouped::<Sale>(&[Aggregate::sum(SaleDef::Amount)], wrapper)
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].0["region"], "n".into());
+
+    let injected = "1 or 1 = 1";
+    let wrapper = Wrapper::new()
+        .group_by(SaleDef::Region)
+        .having_with("max(region) = {0}", [injected]);
+    let groups = grapefruit
+        .select_grouped::<Sale>(&[Aggregate::count()], wrapper)
+        .await
+        .unwrap();
+    assert!(groups.is_empty());
+}
+
+#[tokio::test]
+async fn test_group_by_without_conditions() {
+    let grapefruit = setup("group_by_without_conditions").await;
+    for name in ["a", "b", "a"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+
+    let wrapper = Wrapper::new()
+        .group_by(ItemDef::Name)
+        .having_ge(Aggregate::count(), 2);
+    let groups = grapefruit
+        .select_grouped::<Item>(&[Aggregate::count()], wrapper)
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].0["name"], "a".into());
+    assert_eq!(groups[0].1["count"], 2i64.into());
+}
+
+#[tokio::test]
+async fn test_raw_sql_named_params() {
+    let grapefruit = setup("raw_sql_named_params").await;
+    for name in ["a", "b", "c", ":names"] {
+        grapefruit.insert(&item(name)).await.unwrap();
+    }
+
+    let items: Vec<Item> = grapefruit
+        .query_as(
+            "SELECT id, name FROM t_item WHERE name IN (:names) AND name <> ':names' \
+             AND name <> :skip ORDER BY name",
+            params! { "names" => vec!["a".to_string(), "b".into(), "c".into()], "skip" => "b" },
+        )
+        .await
+        .unwrap();
+    let names = items.iter().map(|i| i.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["a", "c"]);
+
+    let rows = grapefruit
+        .execute_raw(
+            "UPDATE t_item SET name = :name || '!' WHERE name = :name OR name = ':name'",
+            params! { "name" => "a" },
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+    let counts: Vec<i64> = grapefruit
+        .query_as("SELECT COUNT(1) FROM t_item WHERE name = 'a!'", params! {})
+        .await
+        .unwrap();
+    assert_eq!(counts, [1]);
+
+    let res = grapefruit
+        .execute_raw("DELETE FROM t_item WHERE name = :missing", params! {})
+        .await;
+    assert!(matches!(res, Err(GrapefruitError::NoSuchValueError(_))));
+}
+
+#[tokio::test]
+async fn test_map_rows() {
+    let grapefruit = setup("map_rows").await;
+    let contact = Contact {
+        id: None,
+        name: Some("a".into()),
+        addr: Some("x".into()),
+        note: None,
+    };
+    grapefruit.insert(&contact).await.unwrap();
+
+    let rows = grapefruit
+        .select_maps::<Contact>(Wrapper::new().eq(ContactDef::Name, "a"))
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert!(matches!(rows[0]["id"], grapefruit::Value::Bigint(Some(_))));
+    assert_eq!(rows[0]["name"], "a".into());
+    assert_eq!(rows[0]["note"], grapefruit::Value::String(None));
+
+    let rows = grapefruit
+        .query_maps(
+            "SELECT :n + 1 AS i, 1.5 AS r, NULL AS n, x'00ff' AS b, 'y' AS s",
+            params! { "n" => 41 },
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["i"], 42i64.into());
+    assert_eq!(rows[0]["r"], 1.5f64.into());
+    assert!(rows[0]["n"].is_none());
+    assert_eq!(rows[0]["b"], vec![0u8, 255].into());
+    assert_eq!(rows[0]["s"], "y".into());
+
+    grapefruit
+        .pool()
+        .execute(
+            "CREATE TABLE t_price (n NUMERIC, d DECIMAL(10, 2), u UUID)",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+    grapefruit
+        .pool()
+        .execute(
+            "INSERT INTO t_price VALUES (3, 1.5, x'00ff'), (NULL, 'n/a', 'a-b')",
+            Params::Null,
+        )
+        .await
+        .unwrap();
+    let rows = grapefruit
+        .query_maps("SELECT n, d, u FROM t_price ORDER BY n DESC", params! {})
+        .await
+        .unwrap();
+    assert_eq!(rows[0]["n"], 3i64.into());
+    assert_eq!(rows[0]["d"], 1.5f64.into());
+    assert_eq!(rows[0]["u"], vec![0u8, 255].into());
+    assert!(rows[1]["n"].is_none());
+    assert_eq!(rows[1]["d"], "n/a".into());
+    assert_eq!(rows[1]["u"], "a-b".into());
+}
+
+#[tokio::test]
+async fn test_select_stream() {
+    let grapefruit = setup("select_stream").await;
+    let items = (0..500)
+        .map(|i| item(&format!("item-{}", i)))
+        .collect::<Vec<_>>();
+    let refs = items.iter().collect::<Vec<_>>();
+    grapefruit.insert_batch(&refs).await.unwrap();
+
+    let names = grapefruit
+        .select_stream::<Item>(Wrapper::new().order_by_asc(ItemDef::Id))
+        .await
+        .map_ok(|item| item.name)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(names.len(), 500);
+    assert_eq!(names[0], "item-0");
+    assert_eq!(names[499], "item-499");
+
+    // dropping the stream early releases the transaction it runs on
+    let tx = grapefruit.begin().await.unwrap();
+    let first = tx
+        .select_stream::<Item>(Wrapper::new())
+        .await
+        .take(3)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(first.len(), 3);
+    assert_eq!(tx.count_all::<Item>().await.unwrap(), 500);
+    tx.commit().await.unwrap();
+
+    let mut failed = grapefruit
+        .select_stream::<Item>(Wrapper::new().sql_last("ORDER BY no_such_column"))
+        .await;
+    assert!(failed.next().await.unwrap().is_err());
+    assert!(failed.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_cursor_page() {
+    let grapefruit = setup("cursor_page").await;
+    let sales = [
+        ("n", 30, 0),
+        ("n", 10, 0),
+        ("s", 20, 0),
+        ("n", 20, 0),
+        ("s", 10, 0),
+        ("s", 99, 1),
+        ("x", 5, 0),
+    ];
+    for (region, amount, deleted) in sales {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount,
+            deleted,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+    let wrapper = Wrapper::new()
+        .eq(SaleDef::Region, "n")
+        .or()
+        .eq(SaleDef::Region, "s")
+        .order_by_desc(SaleDef::Amount);
+    // ties on amount are broken by the id, which follows the insert order
+    let rows = |records: &[Sale]| {
+        records
+            .iter()
+            .map(|s| (s.region.clone(), s.amount))
+            .collect::<Vec<_>>()
+    };
+
+    let first = grapefruit
+        .cursor_page::<Sale>(wrapper.clone(), None, 2, true)
+        .await
+        .unwrap();
+    assert_eq!(first.total, Some(5));
+    assert_eq!(
+        rows(&first.records),
+        vec![("n".to_string(), 30), ("s".to_string(), 20)]
+    );
+    assert!(first.previous.is_none());
+
+    // cursors survive a round trip through their token
+    let token = first.next.unwrap().encode();
+    let second = grapefruit
+        .cursor_page::<Sale>(
+            wrapper.clone(),
+            Some(Cursor::decode(&token).unwrap()),
+            2,
+            false,
+        )
+        .await
+        .unwrap();
+    assert_eq!(second.total, None);
+    assert_eq!(
+        rows(&second.records),
+        vec![("n".to_string(), 20), ("n".to_string(), 10)]
+    );
+
+    let last = grapefruit
+        .cursor_page::<Sale>(wrapper.clone(), second.next, 2, false)
+        .await
+        .unwrap();
+    assert_eq!(rows(&last.records), vec![("s".to_string(), 10)]);
+    assert!(last.next.is_none());
+
+    let back = grapefruit
+        .cursor_page::<Sale>(wrapper.clone(), last.previous, 2, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        rows(&back.records),
+        vec![("n".to_string(), 20), ("n".to_string(), 10)]
+    );
+    let back = grapefruit
+        .cursor_page::<Sale>(wrapper.clone(), back.previous, 2, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        rows(&back.records),
+        vec![("n".to_string(), 30), ("s".to_string(), 20)]
+    );
+    assert!(back.previous.is_none());
+    assert!(back.next.is_some());
+
+    // a cursor only fits the order it was made for
+    let other_order = Wrapper::new().order_by_asc(SaleDef::Region);
+    assert!(grapefruit
+        .cursor_page::<Sale>(other_order, back.next, 2, false)
+        .await
+        .is_err());
+    assert!(Cursor::decode("not a cursor").is_err());
+
+    let nullable_order = Wrapper::new().order_by_asc(ContactDef::Note);
+    let res = grapefruit
+        .cursor_page::<Contact>(nullable_order, None, 2, false)
+        .await;
+    assert!(matches!(res, Err(GrapefruitError::ObjectValidError(_))));
+
+    let wrapper = wrapper.sql_first("/* first */").sql_last("-- last");
+    let page = grapefruit
+        .cursor_page::<Sale>(wrapper, None, 2, true)
+        .await
+        .unwrap();
+    assert_eq!(page.total, Some(5));
+    assert_eq!(page.records.len(), 2);
+}
+
+#[tokio::test]
+async fn test_page_request() {
+    let grapefruit = setup("page_request").await;
+    for (region, amount, deleted) in [("n", 30, 0), ("s", 10, 0), ("n", 20, 0), ("s", 99, 1)] {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount,
+            deleted,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+
+    let request = PageRequest::new(1, 2).order_by_desc("amount");
+    let page = grapefruit
+        .page_by_request::<Sale>(request, Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(page.total, 3);
+    assert_eq!(page.pages(), 2);
+    assert!(page.has_next());
+    assert!(!page.has_prev());
+    let amounts = page.into_map(|sale| sale.amount);
+    assert_eq!(amounts.records, vec![30, 20]);
+    assert_eq!(amounts.total, 3);
+
+    // the request's order comes before the wrapper's
+    let request = PageRequest::new(2, 2)
+        .order_by_asc("region")
+        .search_count(false);
+    let wrapper = Wrapper::new().order_by_asc(SaleDef::Amount);
+    let page = grapefruit
+        .page_by_request::<Sale>(request, wrapper)
+        .await
+        .unwrap();
+    assert_eq!(page.total, 0);
+    assert!(!page.has_next());
+    assert!(page.has_prev());
+    assert_eq!(page.map(|sale| sale.amount).records, vec![10]);
+
+    let request = PageRequest::new(1, 1).search_count(false);
+    let page = grapefruit
+        .page_by_request::<Sale>(request, Wrapper::new())
+        .await
+        .unwrap();
+    assert_eq!(page.records.len(), 1);
+    assert!(page.has_next());
+
+    assert!(grapefruit
+        .page_by_wrapper::<Sale>(0, 10, Wrapper::new())
+        .await
+        .is_err());
+    let request = PageRequest::new(1, 10).order_by_asc("amount; DROP TABLE t_sale");
+    assert!(grapefruit
+        .page_by_request::<Sale>(request, Wrapper::new())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_soft_delete_scopes() {
+    let grapefruit = setup("soft_delete_scopes").await;
+    for region in ["n", "s", "e"] {
+        let sale = Sale {
+            id: None,
+            region: region.into(),
+            amount: 1,
+            deleted: 0,
+        };
+        grapefruit.insert(&sale).await.unwrap();
+    }
+    let deleted = Wrapper::new()
+        .eq(SaleDef::Region, "n")
+        .or()
+        .eq(SaleDef::Region, "s");
+    assert!(grapefruit.delete_by_wrapper::<Sale>(deleted).await.unwrap());
+
+    let count = |wrapper: Wrapper| grapefruit.count_by_wrapper::<Sale>(wrapper);
+    assert_eq!(count(Wrapper::new()).await.unwrap(), 1);
+    assert_eq!(count(Wrapper::new().with_deleted()).await.unwrap(), 3);
+    assert_eq!(count(Wrapper::new().only_deleted()).await.unwrap(), 2);
+    let sales: Vec<Sale> = grapefruit
+        .select_by_wrapper(Wrapper::new().only_deleted().order_by_asc(SaleDef::Region))
+        .await
+        .unwrap();
+    assert_eq!(sales.len(), 2);
+    assert_eq!(sales[0].region, "n");
+    assert_eq!(sales[0].deleted, 1);
+
+    // the wrapper's `or` stays within the deleted rows
+    let restored = grapefruit
+        .restore_by_wrapper::<Sale>(
+            Wrapper::new()
+                .eq(SaleDef::Region, "n")
+                .or()
+                .eq(SaleDef::Region, "e"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(restored, 1);
+    assert!(grapefruit
+        .restore_by_id::<Sale, _>(sales[1].id.unwrap())
+        .await
+        .unwrap());
+    // restoring a record that is not deleted changes nothing
+    assert!(!grapefruit
+        .restore_by_id::<Sale, _>(sales[1].id.unwrap())
+        .await
+        .unwrap());
+    assert_eq!(count(Wrapper::new()).await.unwrap(), 3);
+
+    // the `or` of a delete or an update stays within its scope as well
+    let deleted = Wrapper::new().eq(SaleDef::Region, "s");
+    assert!(grapefruit.delete_by_wrapper::<Sale>(deleted).await.unwrap());
+    let deleted = Wrapper::new()
+        .only_deleted()
+        .eq(SaleDef::Region, "n")
+        .or()
+        .eq(SaleDef::Region, "s");
+    assert!(grapefruit.delete_by_wrapper::<Sale>(deleted).await.unwrap());
+    assert_eq!(count(Wrapper::new()).await.unwrap(), 2);
+    let sale = Sale {
+        id: None,
+        region: "w".into(),
+        amount: 7,
+        deleted: 0,
+    };
+    let updated = Wrapper::new()
+        .eq(SaleDef::Region, "s")
+        .or()
+        .eq(SaleDef::Region, "e");
+    assert_eq!(
+        grapefruit.update_by_wrapper(&sale, updated).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        count(Wrapper::new().eq(SaleDef::Region, "w"))
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(count(Wrapper::new()).await.unwrap(), 2);
+    let restored = grapefruit
+        .restore_by_wrapper::<Sale>(Wrapper::new().eq(SaleDef::Region, "s"))
+        .await
+        .unwrap();
+    assert_eq!(restored, 1);
+
+    assert!(grapefruit
+        .force_delete_by_id::<Sale, _>(sales[0].id.unwrap())
+        .await
+        .unwrap());
+    assert_eq!(count(Wrapper::new().with_deleted()).await.unwrap(), 2);
+
+    assert!(grapefruit
+        .restore_by_wrapper::<Item>(Wrapper::new())
+        .await
+        .is_err());
+}